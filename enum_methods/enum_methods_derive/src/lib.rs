@@ -1,23 +1,18 @@
-use std::error::Error;
-
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
-use syn::{parse_macro_input, punctuated::Punctuated, token::Comma, DeriveInput, Variant};
-
-type Res<T> = Result<T, Box<dyn Error>>;
+use syn::{parse_macro_input, DeriveInput};
 
 #[proc_macro_derive(EnumMethods)]
 pub fn enum_methods_derive(input: TokenStream) -> TokenStream {
     // Construct a representation of Rust code as a syntax tree
     // that we can manipulate
     let ast = parse_macro_input!(input as DeriveInput);
-    println!("{:?}", &ast);
     let name = &ast.ident;
     let variants = match &ast.data {
         syn::Data::Enum(x) => &x.variants,
         _ => todo!(),
     };
-    let mut inner_count = 0;
+    let mut inner_count = 0usize;
     let inner = match &variants.first().unwrap().fields {
         syn::Fields::Unnamed(x) => {
             x.unnamed.iter().for_each(|_| inner_count += 1);
@@ -35,6 +30,20 @@ pub fn enum_methods_derive(input: TokenStream) -> TokenStream {
     let args_tokens = quote! {
         #(#args),*
     };
+    let indices = 0..inner_count;
+    let mut_arms = variant_names
+        .iter()
+        .map(|variant| {
+            let indices = indices.clone();
+            let args = &args;
+            quote! {
+                Self::#variant(#args_tokens) => match index {
+                    #(#indices => #args,)*
+                    _ => panic!("argument index {} out of bounds for {}", index, #inner_count),
+                }
+            }
+        })
+        .collect::<Vec<_>>();
 
     // Build the trait implementation
     let gen = quote! {
@@ -45,8 +54,33 @@ pub fn enum_methods_derive(input: TokenStream) -> TokenStream {
                     #(Self::#variant_names(#args_tokens) => [#args_tokens][index]),*
                 }
             }
-            fn set_arg<S: Into<usize>>(&self, i: S, value: #inner) {
-                todo!();
+            fn get_arg_mut<S: Into<usize>>(&mut self, i: S) -> &mut #inner {
+                let index: usize = i.into();
+                match self {
+                    #(#mut_arms),*
+                }
+            }
+            fn set_arg<S: Into<usize>>(&mut self, i: S, value: #inner) -> #inner {
+                std::mem::replace(self.get_arg_mut(i), value)
+            }
+            fn arity(&self) -> usize {
+                #inner_count
+            }
+            fn args<'a>(&'a self) -> impl Iterator<Item = &'a #inner>
+            where
+                #inner: 'a,
+            {
+                match self {
+                    #(Self::#variant_names(#args_tokens) => [#args_tokens].into_iter()),*
+                }
+            }
+            fn args_mut<'a>(&'a mut self) -> impl Iterator<Item = &'a mut #inner>
+            where
+                #inner: 'a,
+            {
+                match self {
+                    #(Self::#variant_names(#args_tokens) => [#args_tokens].into_iter()),*
+                }
             }
         }
     };
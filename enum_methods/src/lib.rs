@@ -1,4 +1,14 @@
 pub trait EnumMethods<T> {
     fn get_arg<S: Into<usize>>(&self, i: S) -> &T;
-    fn set_arg<S: Into<usize>>(&self, i: S, value: T);
+    fn get_arg_mut<S: Into<usize>>(&mut self, i: S) -> &mut T;
+    /// Replaces the argument at index `i`, returning the previous value
+    fn set_arg<S: Into<usize>>(&mut self, i: S, value: T) -> T;
+    /// Number of arguments held by every variant
+    fn arity(&self) -> usize;
+    fn args<'a>(&'a self) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a;
+    fn args_mut<'a>(&'a mut self) -> impl Iterator<Item = &'a mut T>
+    where
+        T: 'a;
 }
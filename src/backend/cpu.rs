@@ -0,0 +1,65 @@
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+
+use crate::{
+    compute_functions::image::ImageConfig, cpu::program::Program, error::RenderError, gene::Gene,
+};
+
+use super::RenderBackend;
+
+#[derive(Debug, Default)]
+/// Compiles each gene into a `Program` and renders it on every core
+pub struct CpuBackend {
+    /// Threads to render on, or rayon's global pool if `None`
+    pool: Option<ThreadPool>,
+}
+
+impl CpuBackend {
+    /// Renders on a dedicated pool of `threads` threads instead of rayon's global pool
+    pub fn with_threads(threads: usize) -> Result<Self, ThreadPoolBuildError> {
+        let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+        Ok(Self { pool: Some(pool) })
+    }
+}
+
+impl RenderBackend for CpuBackend {
+    fn name(&self) -> &'static str {
+        "cpu"
+    }
+
+    fn render(&self, gene: &Gene, image_config: &ImageConfig) -> Result<Vec<f32>, RenderError> {
+        let program = Program::compile(gene.function())?;
+        Ok(match &self.pool {
+            Some(pool) => pool.install(|| program.render(image_config)),
+            None => program.render(image_config),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compute_functions::image::{Bounds, Resolution},
+        cpu::processing::generate_buffer,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let config = ImageConfig {
+            resolution: Resolution::new(13, 6),
+            bounds: Bounds::new(-1.0, -1.0, 0.0, 2.0, 2.0),
+        };
+        let backend = CpuBackend::with_threads(4).unwrap();
+        let gene = Gene::new(0, "avg(x, y) + rgb(0.5, 0.25, 0)".parse().unwrap());
+        assert_eq!(
+            backend.render(&gene, &config).unwrap(),
+            generate_buffer(&config, gene.function())
+        );
+        let incomplete = Gene::new(0, "sin(_)".parse().unwrap());
+        assert!(matches!(
+            backend.render(&incomplete, &config),
+            Err(RenderError::Placeholder)
+        ));
+    }
+}
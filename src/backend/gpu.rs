@@ -0,0 +1,75 @@
+use pollster::block_on;
+
+use crate::{
+    compute_functions::image::ImageConfig, error::RenderError, gene::Gene,
+    gpu::instance::GpuInstance,
+};
+
+use super::RenderBackend;
+
+#[derive(Debug)]
+/// Renders with wgpu, tiling images that exceed the device limits
+pub struct GpuBackend {
+    gpu: GpuInstance,
+}
+
+impl GpuBackend {
+    pub fn new(gpu: GpuInstance) -> Self {
+        Self { gpu }
+    }
+
+    pub fn gpu(&self) -> &GpuInstance {
+        &self.gpu
+    }
+}
+
+impl RenderBackend for GpuBackend {
+    fn name(&self) -> &'static str {
+        "gpu"
+    }
+
+    fn render(&self, gene: &Gene, image_config: &ImageConfig) -> Result<Vec<f32>, RenderError> {
+        block_on(self.gpu.generate_tiled_buffer(image_config, gene))
+    }
+
+    /// Submits the whole batch at once when a frame fits in a single tile
+    fn render_batch(
+        &self,
+        genes: &[Gene],
+        image_config: &ImageConfig,
+    ) -> Result<Vec<Result<Vec<f32>, RenderError>>, RenderError> {
+        if self.gpu.tiles(image_config).len() > 1 {
+            return Ok(genes
+                .iter()
+                .map(|gene| self.render(gene, image_config))
+                .collect());
+        }
+        block_on(self.gpu.render_batch(genes, image_config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compute_functions::image::{Bounds, Resolution};
+
+    use super::*;
+
+    #[test]
+    fn test_batch_matches_render() {
+        let backend = GpuBackend::new(block_on(GpuInstance::new()).unwrap());
+        let config = ImageConfig {
+            resolution: Resolution::new(9, 7),
+            bounds: Bounds::new(-1.0, -1.0, 0.0, 2.0, 2.0),
+        };
+        let genes = [
+            Gene::new(0, "sin(x * rgb(3, 1, 2)) + y".parse().unwrap()),
+            Gene::new(1, "_ * x".parse().unwrap()),
+        ];
+        let results = backend.render_batch(&genes, &config).unwrap();
+        assert_eq!(
+            results[0].as_ref().unwrap(),
+            &backend.render(&genes[0], &config).unwrap()
+        );
+        assert!(matches!(results[1], Err(RenderError::Placeholder)));
+    }
+}
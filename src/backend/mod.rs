@@ -0,0 +1,77 @@
+use log::{info, warn};
+use pollster::block_on;
+
+use crate::{
+    compute_functions::image::ImageConfig,
+    error::{GpuError, RenderError},
+    gene::Gene,
+    gpu::{config::GpuConfig, instance::GpuInstance},
+};
+
+pub mod cpu;
+pub mod gpu;
+pub mod null;
+
+pub use cpu::CpuBackend;
+pub use gpu::GpuBackend;
+pub use null::NullBackend;
+
+/// Renders genes into interleaved rgb float buffers, laid out row by row
+pub trait RenderBackend {
+    /// Short name for logs, e.g. "gpu"
+    fn name(&self) -> &'static str;
+
+    /// Renders `gene` at any resolution
+    fn render(&self, gene: &Gene, image_config: &ImageConfig) -> Result<Vec<f32>, RenderError>;
+
+    /// Renders several genes at the same resolution, returning a result per gene in order
+    ///
+    /// The outer error is a failure of the backend itself, after which no gene could be rendered
+    fn render_batch(
+        &self,
+        genes: &[Gene],
+        image_config: &ImageConfig,
+    ) -> Result<Vec<Result<Vec<f32>, RenderError>>, RenderError> {
+        Ok(genes
+            .iter()
+            .map(|gene| self.render(gene, image_config))
+            .collect())
+    }
+}
+
+/// Picks the best backend available at runtime, rendering on the gpu if `gpu_config` finds an adapter and on
+/// every cpu core otherwise
+pub fn select_backend(gpu_config: &GpuConfig) -> Result<Box<dyn RenderBackend>, GpuError> {
+    match block_on(GpuInstance::with_config(gpu_config)) {
+        Ok(gpu) => {
+            info!("rendering on the gpu");
+            Ok(Box::new(GpuBackend::new(gpu)))
+        }
+        Err(GpuError::NoAdapter) => {
+            warn!("no gpu adapter found, rendering on the cpu");
+            Ok(Box::new(CpuBackend::default()))
+        }
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compute_functions::image::{Bounds, Resolution};
+
+    use super::*;
+
+    #[test]
+    fn test_select_backend() {
+        let backend = select_backend(&GpuConfig::default()).unwrap();
+        let config = ImageConfig {
+            resolution: Resolution::new(4, 3),
+            bounds: Bounds::new(0.0, 0.0, 0.0, 1.0, 1.0),
+        };
+        let gene = Gene::new(0, "x + y".parse().unwrap());
+        assert_eq!(backend.render(&gene, &config).unwrap().len(), 4 * 3 * 3);
+
+        let no_backends = GpuConfig::default().backends(wgpu::Backends::empty());
+        assert_eq!(select_backend(&no_backends).unwrap().name(), "cpu");
+    }
+}
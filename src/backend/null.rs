@@ -0,0 +1,19 @@
+use crate::{compute_functions::image::ImageConfig, error::RenderError, gene::Gene};
+
+use super::RenderBackend;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// Renders every gene as a flat image of one value without evaluating it, for testing code above rendering
+pub struct NullBackend {
+    pub value: f32,
+}
+
+impl RenderBackend for NullBackend {
+    fn name(&self) -> &'static str {
+        "null"
+    }
+
+    fn render(&self, _gene: &Gene, image_config: &ImageConfig) -> Result<Vec<f32>, RenderError> {
+        Ok(vec![self.value; image_config.pixels() as usize * 3])
+    }
+}
@@ -0,0 +1,174 @@
+use std::mem::discriminant;
+
+use enum_methods::EnumMethods;
+use strum::IntoEnumIterator;
+
+use crate::error::BytecodeError;
+
+use super::{
+    shader_builder::{AddressSpace, ConstantMode, ShaderBuilder},
+    ComputeFunction, ConstantFunction, SingleArgFunction, TwoArgFunction,
+};
+
+/// Values the interpreter can hold at once, which bounds how deeply a function can nest
+pub const STACK_SIZE: usize = 32;
+
+/// Pushes a coordinate, with the dimension added to the opcode
+const OP_COORD: u32 = 0;
+/// Pushes the constant held in the next three words as f32 bits
+const OP_CONSTANT: u32 = 3;
+/// Applies a single argument function, with its `SingleArgFunction::iter` position added to the opcode
+const OP_SINGLE: u32 = 16;
+/// Applies a two argument function, with its `TwoArgFunction::iter` position added to the opcode
+const OP_TWO: u32 = 48;
+
+impl ComputeFunction {
+    /// Serialises to postfix bytecode for the gpu interpreter, arguments before the functions applied to them
+    pub fn bytecode(&self) -> Result<Vec<u32>, BytecodeError> {
+        let mut code = vec![];
+        let mut stack = 0;
+        self.write_bytecode(&mut code, &mut stack)?;
+        Ok(code)
+    }
+
+    /// Writes self, where `stack` is the number of values on the stack before it runs
+    fn write_bytecode(&self, code: &mut Vec<u32>, stack: &mut usize) -> Result<(), BytecodeError> {
+        match self {
+            ComputeFunction::Zero(inner) => {
+                match **inner {
+                    ConstantFunction::Constant(r, g, b) => {
+                        code.extend([OP_CONSTANT, r.to_bits(), g.to_bits(), b.to_bits()])
+                    }
+                    // Dimensions past z read z, as in compiled shaders
+                    ConstantFunction::Coord(dim) => code.push(OP_COORD + dim.min(2) as u32),
+                }
+                *stack += 1;
+                if *stack > STACK_SIZE {
+                    return Err(BytecodeError::StackOverflow(*stack, STACK_SIZE));
+                }
+            }
+            ComputeFunction::One(inner) => {
+                inner.get_arg(0usize).write_bytecode(code, stack)?;
+                code.push(OP_SINGLE + position(SingleArgFunction::iter(), &**inner));
+            }
+            ComputeFunction::Two(inner) => {
+                inner.get_arg(0usize).write_bytecode(code, stack)?;
+                inner.get_arg(1usize).write_bytecode(code, stack)?;
+                code.push(OP_TWO + position(TwoArgFunction::iter(), &**inner));
+                *stack -= 1;
+            }
+            ComputeFunction::Placeholder => return Err(BytecodeError::Placeholder),
+        }
+        Ok(())
+    }
+}
+
+/// Position of `function`'s variant among `variants`
+fn position<T>(mut variants: impl Iterator<Item = T>, function: &T) -> u32 {
+    // Can unwrap because every variant is iterated
+    variants
+        .position(|variant| discriminant(&variant) == discriminant(function))
+        .unwrap() as u32
+}
+
+/// Builds the shader that interprets bytecode, rendering one gene per z index of the dispatch
+///
+/// Binds `result`, `resolution` and `bounds` as frame shaders do, then `code` at 3. `code` starts with the
+/// start and end index of each gene's program, followed by the programs themselves.
+/// Each gene's image follows the last in `result`
+pub fn interpreter_shader() -> String {
+    let mut builder = ShaderBuilder::frame(ConstantMode::Inline);
+    builder.add_binding("code", AddressSpace::Storage, "array<u32>");
+    let top = "stack[top - 1u]";
+    let below = "stack[top - 2u]";
+    let mut cases = vec![];
+    for (dim, name) in ["x", "y", "z"].iter().enumerate() {
+        cases.push((
+            OP_COORD + dim as u32,
+            format!("stack[top] = vec3<f32>({}); top += 1u;", name),
+        ));
+    }
+    cases.push((
+        OP_CONSTANT,
+        "stack[top] = bitcast<vec3<f32>>(vec3<u32>(code[pc], code[pc + 1u], code[pc + 2u])); pc += 3u; top += 1u;"
+            .to_string(),
+    ));
+    for (i, function) in SingleArgFunction::iter().enumerate() {
+        let expression = function.shader_expression(&mut builder, top);
+        cases.push((OP_SINGLE + i as u32, format!("{} = {};", top, expression)));
+    }
+    for (i, function) in TwoArgFunction::iter().enumerate() {
+        let expression = function.shader_expression(&mut builder, below, top);
+        cases.push((
+            OP_TWO + i as u32,
+            format!("{} = {}; top -= 1u;", below, expression),
+        ));
+    }
+
+    builder.add_statement(
+        "let program: vec2<u32> = vec2<u32>(code[global_id.z * 2u], code[global_id.z * 2u + 1u]);",
+    );
+    builder.add_statement(&format!("var stack: array<vec3<f32>, {}>;", STACK_SIZE));
+    builder.add_statement("var top: u32 = 0u;");
+    builder.add_statement("var pc: u32 = program.x;");
+    let mut body = String::from("loop {\n        if pc >= program.y {\n            break;\n        }\n        let op: u32 = code[pc];\n        pc += 1u;\n        switch op {\n");
+    for (op, statement) in cases {
+        body += &format!("            case {}u: {{ {} }}\n", op, statement);
+    }
+    body += "            default: {}\n        }\n    }";
+    builder.add_statement(&body);
+    builder
+        .add_statement("let offset: u32 = index + global_id.z * resolution.x * resolution.y * 3u;");
+    for channel in 0..3 {
+        builder.add_output(&format!(
+            "result[offset + {}u] = stack[0][{}];",
+            channel, channel
+        ));
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compute_functions::shader::validate_shader;
+
+    use super::*;
+
+    #[test]
+    fn test_bytecode() {
+        let function: ComputeFunction = "sin(x) + rgb(1, 2, 3)".parse().unwrap();
+        let sin = OP_SINGLE;
+        let add = OP_TWO;
+        assert_eq!(
+            function.bytecode().unwrap(),
+            vec![
+                OP_COORD,
+                sin,
+                OP_CONSTANT,
+                1.0f32.to_bits(),
+                2.0f32.to_bits(),
+                3.0f32.to_bits(),
+                add
+            ]
+        );
+        assert_eq!(
+            "sin(_)".parse::<ComputeFunction>().unwrap().bytecode(),
+            Err(BytecodeError::Placeholder)
+        );
+        // A right leaning chain keeps every left argument on the stack
+        let mut deep: ComputeFunction = "x".parse().unwrap();
+        for _ in 0..STACK_SIZE {
+            deep = format!("y + ({})", deep).parse().unwrap();
+        }
+        assert_eq!(
+            deep.bytecode(),
+            Err(BytecodeError::StackOverflow(STACK_SIZE + 1, STACK_SIZE))
+        );
+    }
+
+    #[test]
+    fn test_interpreter_validates() {
+        let shader = interpreter_shader();
+        validate_shader(&shader).unwrap_or_else(|e| panic!("{}\n{}", shader, e));
+    }
+}
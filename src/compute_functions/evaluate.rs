@@ -0,0 +1,137 @@
+use super::{ComputeFunction, ConstantFunction, SingleArgFunction, TwoArgFunction};
+
+/// Rgb value produced by a function at a single point
+pub type Rgb = [f32; 3];
+
+pub trait CpuFunction {
+    /// Evaluates the function at a single point on the cpu
+    ///
+    /// Follows the semantics of the generated wgsl so it can be used as a reference for the gpu output
+    fn evaluate(&self, x: f32, y: f32, z: f32) -> Rgb;
+}
+
+fn map(value: Rgb, f: impl Fn(f32) -> f32) -> Rgb {
+    [f(value[0]), f(value[1]), f(value[2])]
+}
+
+fn zip(a: Rgb, b: Rgb, f: impl Fn(f32, f32) -> f32) -> Rgb {
+    [f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2])]
+}
+
+/// Wgsl leaves `pow` undefined for negative bases, which the shader's `power` helper turns into NaN
+pub(crate) fn wgsl_pow(x: f32, y: f32) -> f32 {
+    if x < 0.0 {
+        f32::NAN
+    } else {
+        x.powf(y)
+    }
+}
+
+/// Bitwise operators act on the raw bits of each channel, as if reinterpreted with `bitcast<u32>`
+pub(crate) fn bitwise(a: f32, b: f32, f: impl Fn(u32, u32) -> u32) -> f32 {
+    f32::from_bits(f(a.to_bits(), b.to_bits()))
+}
+
+impl CpuFunction for ComputeFunction {
+    fn evaluate(&self, x: f32, y: f32, z: f32) -> Rgb {
+        match self {
+            ComputeFunction::Zero(arg) => arg.evaluate(x, y, z),
+            ComputeFunction::One(arg) => arg.evaluate(x, y, z),
+            ComputeFunction::Two(arg) => arg.evaluate(x, y, z),
+            // Placeholders have no value, so they give NaN rather than panicking on an incomplete tree
+            ComputeFunction::Placeholder => [f32::NAN; 3],
+        }
+    }
+}
+
+impl CpuFunction for ConstantFunction {
+    fn evaluate(&self, x: f32, y: f32, z: f32) -> Rgb {
+        match self {
+            ConstantFunction::Constant(r, g, b) => [*r, *g, *b],
+            ConstantFunction::Coord(dim) => match dim {
+                0 => [x; 3],
+                1 => [y; 3],
+                _ => [z; 3],
+            },
+        }
+    }
+}
+
+impl CpuFunction for SingleArgFunction {
+    fn evaluate(&self, x: f32, y: f32, z: f32) -> Rgb {
+        match self {
+            SingleArgFunction::Sin(arg) => map(arg.evaluate(x, y, z), f32::sin),
+            SingleArgFunction::Cos(arg) => map(arg.evaluate(x, y, z), f32::cos),
+            SingleArgFunction::Tan(arg) => map(arg.evaluate(x, y, z), f32::tan),
+            SingleArgFunction::Atan(arg) => map(arg.evaluate(x, y, z), f32::atan),
+            SingleArgFunction::Sinh(arg) => map(arg.evaluate(x, y, z), f32::sinh),
+            SingleArgFunction::Cosh(arg) => map(arg.evaluate(x, y, z), f32::cosh),
+            SingleArgFunction::Abs(arg) => map(arg.evaluate(x, y, z), f32::abs),
+            SingleArgFunction::Reciprocal(arg) => map(arg.evaluate(x, y, z), |v| 1.0 / v),
+            SingleArgFunction::Square(arg) => map(arg.evaluate(x, y, z), |v| v * v),
+            // Negative inputs give NaN for both sqrt and log, matching wgsl
+            SingleArgFunction::SquareRoot(arg) => map(arg.evaluate(x, y, z), f32::sqrt),
+            SingleArgFunction::Loge(arg) => map(arg.evaluate(x, y, z), f32::ln),
+        }
+    }
+}
+
+impl CpuFunction for TwoArgFunction {
+    fn evaluate(&self, x: f32, y: f32, z: f32) -> Rgb {
+        let (f, arg1, arg2): (fn(f32, f32) -> f32, _, _) = match self {
+            TwoArgFunction::Add(arg1, arg2) => (|a, b| a + b, arg1, arg2),
+            TwoArgFunction::Subtract(arg1, arg2) => (|a, b| a - b, arg1, arg2),
+            TwoArgFunction::Multiply(arg1, arg2) => (|a, b| a * b, arg1, arg2),
+            TwoArgFunction::Divide(arg1, arg2) => (|a, b| a / b, arg1, arg2),
+            TwoArgFunction::Min(arg1, arg2) => (f32::min, arg1, arg2),
+            TwoArgFunction::Max(arg1, arg2) => (f32::max, arg1, arg2),
+            TwoArgFunction::Avg(arg1, arg2) => (|a, b| (a + b) / 2.0, arg1, arg2),
+            // Wgsl `%` on floats truncates like rust's, i.e. `a - b * trunc(a / b)`
+            TwoArgFunction::Mod(arg1, arg2) => (|a, b| a % b, arg1, arg2),
+            TwoArgFunction::Exponent(arg1, arg2) => (wgsl_pow, arg1, arg2),
+            TwoArgFunction::And(arg1, arg2) => (|a, b| bitwise(a, b, |a, b| a & b), arg1, arg2),
+            TwoArgFunction::Or(arg1, arg2) => (|a, b| bitwise(a, b, |a, b| a | b), arg1, arg2),
+            TwoArgFunction::Xor(arg1, arg2) => (|a, b| bitwise(a, b, |a, b| a ^ b), arg1, arg2),
+        };
+        zip(arg1.evaluate(x, y, z), arg2.evaluate(x, y, z), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compute_functions::test_function;
+
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let compute_function = test_function();
+        let result = compute_function.evaluate(0.5, 0.0, 0.0);
+        let expected = [
+            0.5f32.sin() + 0.1f32.ln(),
+            0.5f32.sin() + 0.2f32.ln(),
+            0.5f32.sin() + 0.3f32.ln(),
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_wgsl_semantics() {
+        let negative =
+            ComputeFunction::Zero(Box::new(ConstantFunction::Constant(-2.0, -2.0, -2.0)));
+        let log = SingleArgFunction::Loge(negative.clone());
+        assert!(log.evaluate(0.0, 0.0, 0.0).iter().all(|v| v.is_nan()));
+        let pow = TwoArgFunction::Exponent(negative.clone(), negative.clone());
+        assert!(pow.evaluate(0.0, 0.0, 0.0).iter().all(|v| v.is_nan()));
+        let modulo = TwoArgFunction::Mod(
+            negative,
+            ComputeFunction::Zero(Box::new(ConstantFunction::Constant(1.5, 1.5, 1.5))),
+        );
+        assert_eq!(modulo.evaluate(0.0, 0.0, 0.0), [-0.5; 3]);
+        let incomplete: ComputeFunction = "x + _".parse().unwrap();
+        assert!(incomplete
+            .evaluate(0.0, 0.0, 0.0)
+            .iter()
+            .all(|v| v.is_nan()));
+    }
+}
@@ -0,0 +1,455 @@
+use std::{fmt, str::FromStr};
+
+use crate::error::ParseError;
+
+use super::{ComputeFunction, ConstantFunction, SingleArgFunction, TwoArgFunction};
+
+// Binding strength of each kind of node when written infix
+const ADDITIVE: u8 = 1;
+const MULTIPLICATIVE: u8 = 2;
+const ATOM: u8 = 3;
+
+/// Deepest nesting of parentheses and calls the parser accepts, low enough for deep input not to overflow a
+/// 2 MiB thread stack in debug builds
+pub const MAX_NESTING: usize = 256;
+
+impl SingleArgFunction {
+    /// Name used in the expression syntax
+    pub fn name(&self) -> &'static str {
+        match self {
+            SingleArgFunction::Sin(_) => "sin",
+            SingleArgFunction::Cos(_) => "cos",
+            SingleArgFunction::Tan(_) => "tan",
+            SingleArgFunction::Atan(_) => "atan",
+            SingleArgFunction::Sinh(_) => "sinh",
+            SingleArgFunction::Cosh(_) => "cosh",
+            SingleArgFunction::Abs(_) => "abs",
+            SingleArgFunction::Reciprocal(_) => "recip",
+            SingleArgFunction::Square(_) => "sq",
+            SingleArgFunction::SquareRoot(_) => "sqrt",
+            SingleArgFunction::Loge(_) => "log",
+        }
+    }
+
+    fn from_name(name: &str, arg: ComputeFunction) -> Option<Self> {
+        Some(match name {
+            "sin" => SingleArgFunction::Sin(arg),
+            "cos" => SingleArgFunction::Cos(arg),
+            "tan" => SingleArgFunction::Tan(arg),
+            "atan" => SingleArgFunction::Atan(arg),
+            "sinh" => SingleArgFunction::Sinh(arg),
+            "cosh" => SingleArgFunction::Cosh(arg),
+            "abs" => SingleArgFunction::Abs(arg),
+            "recip" => SingleArgFunction::Reciprocal(arg),
+            "sq" => SingleArgFunction::Square(arg),
+            "sqrt" => SingleArgFunction::SquareRoot(arg),
+            "log" => SingleArgFunction::Loge(arg),
+            _ => return None,
+        })
+    }
+}
+
+impl TwoArgFunction {
+    /// Name used in the expression syntax, which is an infix operator for arithmetic
+    pub fn name(&self) -> &'static str {
+        match self {
+            TwoArgFunction::Add(..) => "+",
+            TwoArgFunction::Subtract(..) => "-",
+            TwoArgFunction::Multiply(..) => "*",
+            TwoArgFunction::Divide(..) => "/",
+            TwoArgFunction::Min(..) => "min",
+            TwoArgFunction::Max(..) => "max",
+            TwoArgFunction::Avg(..) => "avg",
+            TwoArgFunction::Mod(..) => "%",
+            TwoArgFunction::Exponent(..) => "pow",
+            TwoArgFunction::And(..) => "and",
+            TwoArgFunction::Or(..) => "or",
+            TwoArgFunction::Xor(..) => "xor",
+        }
+    }
+
+    fn from_name(name: &str, arg1: ComputeFunction, arg2: ComputeFunction) -> Option<Self> {
+        Some(match name {
+            "+" => TwoArgFunction::Add(arg1, arg2),
+            "-" => TwoArgFunction::Subtract(arg1, arg2),
+            "*" => TwoArgFunction::Multiply(arg1, arg2),
+            "/" => TwoArgFunction::Divide(arg1, arg2),
+            "min" => TwoArgFunction::Min(arg1, arg2),
+            "max" => TwoArgFunction::Max(arg1, arg2),
+            "avg" => TwoArgFunction::Avg(arg1, arg2),
+            "%" => TwoArgFunction::Mod(arg1, arg2),
+            "pow" => TwoArgFunction::Exponent(arg1, arg2),
+            "and" => TwoArgFunction::And(arg1, arg2),
+            "or" => TwoArgFunction::Or(arg1, arg2),
+            "xor" => TwoArgFunction::Xor(arg1, arg2),
+            _ => return None,
+        })
+    }
+
+    /// Precedence when written infix, or `None` for functions written as calls
+    fn precedence(&self) -> Option<u8> {
+        match self {
+            TwoArgFunction::Add(..) | TwoArgFunction::Subtract(..) => Some(ADDITIVE),
+            TwoArgFunction::Multiply(..) | TwoArgFunction::Divide(..) | TwoArgFunction::Mod(..) => {
+                Some(MULTIPLICATIVE)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ComputeFunction {
+    fn precedence(&self) -> u8 {
+        match self {
+            ComputeFunction::Two(inner) => inner.precedence().unwrap_or(ATOM),
+            _ => ATOM,
+        }
+    }
+
+    /// Writes self, wrapped in parentheses if it binds looser than `min_precedence`
+    fn write_expr(&self, f: &mut fmt::Formatter<'_>, min_precedence: u8) -> fmt::Result {
+        let precedence = self.precedence();
+        if precedence < min_precedence {
+            write!(f, "(")?;
+        }
+        match self {
+            ComputeFunction::Zero(inner) => write!(f, "{}", inner)?,
+            ComputeFunction::One(inner) => {
+                write!(f, "{}(", inner.name())?;
+                self.children()[0].write_expr(f, 0)?;
+                write!(f, ")")?;
+            }
+            ComputeFunction::Two(inner) => {
+                let children = self.children();
+                if inner.precedence().is_some() {
+                    children[0].write_expr(f, precedence)?;
+                    write!(f, " {} ", inner.name())?;
+                    // Operators are left associative, so an equal right operand keeps its parentheses
+                    children[1].write_expr(f, precedence + 1)?;
+                } else {
+                    write!(f, "{}(", inner.name())?;
+                    children[0].write_expr(f, 0)?;
+                    write!(f, ", ")?;
+                    children[1].write_expr(f, 0)?;
+                    write!(f, ")")?;
+                }
+            }
+            ComputeFunction::Placeholder => write!(f, "_")?,
+        }
+        if precedence < min_precedence {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ConstantFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstantFunction::Constant(r, g, b) => write!(f, "rgb({}, {}, {})", r, g, b),
+            ConstantFunction::Coord(0) => write!(f, "x"),
+            ConstantFunction::Coord(1) => write!(f, "y"),
+            ConstantFunction::Coord(2) => write!(f, "z"),
+            ConstantFunction::Coord(dim) => write!(f, "coord({})", dim),
+        }
+    }
+}
+
+impl fmt::Display for ComputeFunction {
+    /// Writes the tree in infix syntax, e.g. `sin(x) + log(rgb(0.1, 0.2, 0.3))`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_expr(f, 0)
+    }
+}
+
+impl FromStr for ComputeFunction {
+    type Err = ParseError;
+
+    /// Parses the syntax written by `Display`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            input: s,
+            position: 0,
+            depth: 0,
+        };
+        let function = parser.expression()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            Some(c) => Err(ParseError::UnexpectedChar(parser.position, c)),
+            None => Ok(function),
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+    /// Atoms currently being parsed, each of which may recurse into a nested expression
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.position += c.len_utf8();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += c.len_utf8();
+                Ok(())
+            }
+            Some(c) => Err(ParseError::UnexpectedChar(self.position, c)),
+            None => Err(ParseError::UnexpectedEnd(self.position)),
+        }
+    }
+
+    /// Consumes the next character if it is one of `operators`
+    fn operator(&mut self, operators: &str) -> Option<char> {
+        self.skip_whitespace();
+        let c = self.peek().filter(|c| operators.contains(*c))?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    /// Parses a left associative chain of binary operators
+    fn binary(
+        &mut self,
+        operators: &str,
+        operand: fn(&mut Self) -> Result<ComputeFunction, ParseError>,
+    ) -> Result<ComputeFunction, ParseError> {
+        let mut left = operand(self)?;
+        while let Some(op) = self.operator(operators) {
+            let right = operand(self)?;
+            // Can unwrap because every operator character names a function
+            let function = TwoArgFunction::from_name(&op.to_string(), left, right).unwrap();
+            left = ComputeFunction::Two(Box::new(function));
+        }
+        Ok(left)
+    }
+
+    fn expression(&mut self) -> Result<ComputeFunction, ParseError> {
+        self.binary("+-", Self::term)
+    }
+
+    fn term(&mut self) -> Result<ComputeFunction, ParseError> {
+        self.binary("*/%", Self::atom)
+    }
+
+    fn identifier(&mut self) -> &str {
+        let start = self.position;
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            self.position += c.len_utf8();
+        }
+        &self.input[start..self.position]
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, ParseError> {
+        self.skip_whitespace();
+        let start = self.position;
+        let mut previous = None;
+        while let Some(c) = self.peek() {
+            let sign = (c == '-' || c == '+')
+                && (self.position == start || matches!(previous, Some('e' | 'E')));
+            if !(c.is_ascii_alphanumeric() || c == '.' || sign) {
+                break;
+            }
+            previous = Some(c);
+            self.position += c.len_utf8();
+        }
+        let text = &self.input[start..self.position];
+        if text.is_empty() {
+            return match self.peek() {
+                Some(c) => Err(ParseError::UnexpectedChar(start, c)),
+                None => Err(ParseError::UnexpectedEnd(start)),
+            };
+        }
+        text.parse()
+            .map_err(|_| ParseError::InvalidNumber(start, text.to_string()))
+    }
+
+    /// Parses comma separated arguments up to the closing parenthesis of a call
+    fn arguments(&mut self) -> Result<Vec<ComputeFunction>, ParseError> {
+        let mut args = vec![self.expression()?];
+        while self.operator(",").is_some() {
+            args.push(self.expression()?);
+        }
+        self.expect(')')?;
+        Ok(args)
+    }
+
+    fn atom(&mut self) -> Result<ComputeFunction, ParseError> {
+        if self.depth == MAX_NESTING {
+            return Err(ParseError::TooDeep(self.position, MAX_NESTING));
+        }
+        self.depth += 1;
+        let atom = self.nested_atom();
+        self.depth -= 1;
+        atom
+    }
+
+    fn nested_atom(&mut self) -> Result<ComputeFunction, ParseError> {
+        self.skip_whitespace();
+        let start = self.position;
+        match self.peek() {
+            None => return Err(ParseError::UnexpectedEnd(start)),
+            Some('(') => {
+                self.position += 1;
+                let inner = self.expression()?;
+                self.expect(')')?;
+                return Ok(inner);
+            }
+            Some(c) if !(c.is_ascii_alphabetic() || c == '_') => {
+                return Err(ParseError::UnexpectedChar(start, c))
+            }
+            _ => {}
+        }
+        let name = self.identifier().to_string();
+        let constant = |c| Ok(ComputeFunction::Zero(Box::new(c)));
+        match name.as_str() {
+            "_" => return Ok(ComputeFunction::Placeholder),
+            "x" => return constant(ConstantFunction::Coord(0)),
+            "y" => return constant(ConstantFunction::Coord(1)),
+            "z" => return constant(ConstantFunction::Coord(2)),
+            _ => {}
+        }
+        self.expect('(')?;
+        match name.as_str() {
+            "coord" => {
+                let dim = self.number()?;
+                self.expect(')')?;
+                constant(ConstantFunction::Coord(dim))
+            }
+            "rgb" => {
+                let r = self.number()?;
+                self.expect(',')?;
+                let g = self.number()?;
+                self.expect(',')?;
+                let b = self.number()?;
+                self.expect(')')?;
+                constant(ConstantFunction::Constant(r, g, b))
+            }
+            _ => {
+                let args_position = self.position;
+                let mut args = self.arguments()?;
+                let function = match args.len() {
+                    1 => SingleArgFunction::from_name(&name, args.remove(0))
+                        .map(|f| ComputeFunction::One(Box::new(f))),
+                    2 => {
+                        let arg2 = args.remove(1);
+                        TwoArgFunction::from_name(&name, args.remove(0), arg2)
+                            .map(|f| ComputeFunction::Two(Box::new(f)))
+                    }
+                    _ => None,
+                };
+                function.ok_or_else(|| {
+                    // Distinguish a known function given the wrong number of arguments from an unknown one
+                    let placeholder = ComputeFunction::Placeholder;
+                    if SingleArgFunction::from_name(&name, placeholder.clone()).is_some() {
+                        ParseError::WrongArgCount(args_position, 1)
+                    } else if TwoArgFunction::from_name(&name, placeholder.clone(), placeholder)
+                        .is_some()
+                    {
+                        ParseError::WrongArgCount(args_position, 2)
+                    } else {
+                        ParseError::UnknownFunction(start, name)
+                    }
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use crate::{compute_functions::utils::GenerationMethod, gene::GeneRng};
+
+    use super::*;
+
+    fn round_trip(function: &ComputeFunction) -> ComputeFunction {
+        function.to_string().parse().unwrap()
+    }
+
+    #[test]
+    fn test_display() {
+        let function: ComputeFunction = "sin(x) + log(rgb(0.1,0.2,0.3))".parse().unwrap();
+        assert_eq!(function.to_string(), "sin(x) + log(rgb(0.1, 0.2, 0.3))");
+        let function: ComputeFunction = "(x - y) - (x - z) * pow(y, rgb(2, 2, 2) * x)"
+            .parse()
+            .unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(
+            function.to_string(),
+            "x - y - (x - z) * pow(y, rgb(2, 2, 2) * x)"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = GeneRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let function =
+                ComputeFunction::random_deep(GenerationMethod::Grow, 1, 6, &mut rng).unwrap();
+            let parsed = round_trip(&function);
+            assert_eq!(
+                bincode::serialize(&function).unwrap(),
+                bincode::serialize(&parsed).unwrap(),
+                "{}",
+                function
+            );
+        }
+        let edge_cases = "x - (y - z) + x / (y * z) % coord(7) - rgb(-1e-7, inf, -0.5)";
+        let function: ComputeFunction = edge_cases.parse().unwrap();
+        assert_eq!(
+            function
+                .to_string()
+                .parse::<ComputeFunction>()
+                .unwrap()
+                .to_string(),
+            function.to_string()
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |s: &str| s.parse::<ComputeFunction>().unwrap_err();
+        assert_eq!(error("sin(x"), ParseError::UnexpectedEnd(5));
+        assert_eq!(
+            error("foo(x)"),
+            ParseError::UnknownFunction(0, "foo".to_string())
+        );
+        assert_eq!(error("x + sin(x, y)"), ParseError::WrongArgCount(8, 1));
+        assert_eq!(
+            error("rgb(1, 2, a)"),
+            ParseError::InvalidNumber(10, "a".to_string())
+        );
+        assert_eq!(error("x y"), ParseError::UnexpectedChar(2, 'y'));
+        assert_eq!(error("x + ").position(), 4);
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |depth: usize| format!("{}x{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(nested(MAX_NESTING - 1).parse::<ComputeFunction>().is_ok());
+        assert_eq!(
+            nested(MAX_NESTING).parse::<ComputeFunction>(),
+            Err(ParseError::TooDeep(MAX_NESTING, MAX_NESTING))
+        );
+        let calls = format!("{}x{}", "sin(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(
+            calls.parse::<ComputeFunction>(),
+            Err(ParseError::TooDeep(MAX_NESTING * 4, MAX_NESTING))
+        );
+    }
+}
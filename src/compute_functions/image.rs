@@ -1,13 +1,13 @@
 use serde::Serialize;
 
 #[repr(C)]
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub struct Bounds {
-    x: f32,
-    y: f32,
-    z: f32,
-    w: f32,
-    h: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+    pub h: f32,
 }
 
 impl Bounds {
@@ -17,7 +17,7 @@ impl Bounds {
 }
 
 #[repr(C)]
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub struct Resolution(pub u32, pub u32);
 
 impl Resolution {
@@ -26,7 +26,119 @@ impl Resolution {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ImageConfig {
     pub resolution: Resolution,
     pub bounds: Bounds,
 }
+
+impl ImageConfig {
+    pub fn pixels(&self) -> u32 {
+        self.resolution.0 * self.resolution.1
+    }
+
+    /// Returns the (x, y, z) coordinates sampled for pixel `index`, counting row by row
+    pub fn coordinates(&self, index: u32) -> (f32, f32, f32) {
+        let column = index % self.resolution.0;
+        let row = index / self.resolution.0;
+        let x = column as f32 / self.resolution.0 as f32 * self.bounds.w + self.bounds.x;
+        let y = row as f32 / self.resolution.1 as f32 * self.bounds.h + self.bounds.y;
+        (x, y, self.bounds.z)
+    }
+
+    /// Splits the image into a grid of tiles, each at most `max_side` pixels wide and tall and holding at most
+    /// `max_pixels` pixels
+    ///
+    /// Tiles span full rows where possible, and are listed row by row
+    pub fn tiles(&self, max_pixels: u32, max_side: u32) -> Vec<Tile> {
+        let Resolution(width, height) = self.resolution;
+        let tile_width = width.min(max_side).min(max_pixels).max(1);
+        let tile_height = height.min(max_side).min(max_pixels / tile_width).max(1);
+        let mut tiles = vec![];
+        for row in (0..height).step_by(tile_height as usize) {
+            for column in (0..width).step_by(tile_width as usize) {
+                let resolution = Resolution::new(
+                    tile_width.min(width - column),
+                    tile_height.min(height - row),
+                );
+                let bounds = Bounds {
+                    x: self.bounds.x + column as f32 / width as f32 * self.bounds.w,
+                    y: self.bounds.y + row as f32 / height as f32 * self.bounds.h,
+                    z: self.bounds.z,
+                    w: resolution.0 as f32 / width as f32 * self.bounds.w,
+                    h: resolution.1 as f32 / height as f32 * self.bounds.h,
+                };
+                tiles.push(Tile {
+                    column,
+                    row,
+                    config: ImageConfig { resolution, bounds },
+                });
+            }
+        }
+        tiles
+    }
+
+    /// Splits the image into full width bands of at most `rows` rows each
+    pub fn bands(&self, rows: u32) -> Vec<Tile> {
+        self.tiles(self.resolution.0.saturating_mul(rows.max(1)), u32::MAX)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Part of a larger image, sampling the same coordinates as the matching pixels of the full image
+pub struct Tile {
+    /// Pixel column of the tile's left edge in the full image
+    pub column: u32,
+    /// Pixel row of the tile's top edge in the full image
+    pub row: u32,
+    pub config: ImageConfig,
+}
+
+impl Tile {
+    /// Copies a rendered tile into the interleaved rgb buffer of an image `image_width` pixels wide
+    pub fn stitch(&self, tile_buffer: &[f32], image_buffer: &mut [f32], image_width: u32) {
+        let row_length = self.config.resolution.0 as usize * 3;
+        for (i, tile_row) in tile_buffer.chunks_exact(row_length).enumerate() {
+            let start = ((self.row as usize + i) * image_width as usize + self.column as usize) * 3;
+            image_buffer[start..start + row_length].copy_from_slice(tile_row);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiles_cover_image() {
+        let config = ImageConfig {
+            resolution: Resolution::new(23, 10),
+            bounds: Bounds::new(-1.0, 0.5, 0.25, 4.0, 2.0),
+        };
+        for (max_pixels, max_side) in [(1000, 100), (50, 100), (7, 100), (100, 8), (1, 1)] {
+            let tiles = config.tiles(max_pixels, max_side);
+            let mut covered = vec![0; config.pixels() as usize];
+            for tile in &tiles {
+                let Resolution(width, height) = tile.config.resolution;
+                assert!(width * height <= max_pixels && width <= max_side && height <= max_side);
+                for i in 0..tile.config.pixels() {
+                    let (column, row) = (tile.column + i % width, tile.row + i / width);
+                    let index = row * config.resolution.0 + column;
+                    covered[index as usize] += 1;
+                    let (x, y, z) = tile.config.coordinates(i);
+                    let (ex, ey, ez) = config.coordinates(index);
+                    assert!((x - ex).abs() < 1e-5 && (y - ey).abs() < 1e-5 && z == ez);
+                }
+            }
+            assert!(covered.iter().all(|c| *c == 1));
+        }
+        assert_eq!(
+            config.tiles(1000, 100),
+            vec![Tile {
+                column: 0,
+                row: 0,
+                config
+            }]
+        );
+    }
+}
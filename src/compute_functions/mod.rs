@@ -3,11 +3,19 @@ use enum_methods_derive::EnumMethods;
 use serde::{Deserialize, Serialize};
 use strum::EnumIter;
 
+pub mod bytecode;
+pub mod evaluate;
+pub mod expression;
 pub mod image;
+pub mod path;
 pub mod shader;
+pub mod shader_builder;
+pub mod simplify;
+#[cfg(test)]
+pub(crate) mod test_utils;
 pub mod utils;
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 pub enum ComputeFunction {
     Zero(Box<ConstantFunction>),
     One(Box<SingleArgFunction>),
@@ -16,13 +24,13 @@ pub enum ComputeFunction {
     Placeholder,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum ConstantFunction {
     Constant(f32, f32, f32),
     Coord(u8),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, EnumIter, EnumMethods)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, EnumIter, EnumMethods)]
 pub enum SingleArgFunction {
     Sin(ComputeFunction),
     Cos(ComputeFunction),
@@ -37,7 +45,7 @@ pub enum SingleArgFunction {
     Loge(ComputeFunction),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, EnumIter, EnumMethods)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, EnumIter, EnumMethods)]
 pub enum TwoArgFunction {
     Add(ComputeFunction, ComputeFunction),
     Subtract(ComputeFunction, ComputeFunction),
@@ -52,3 +60,22 @@ pub enum TwoArgFunction {
     Or(ComputeFunction, ComputeFunction),
     Xor(ComputeFunction, ComputeFunction),
 }
+
+#[cfg(test)]
+/// Fixture shared by tests:
+///
+/// Add
+///     Sin
+///         Coord(0)
+///     Loge
+///         Constant(0.1,0.2,0.3)
+pub(crate) fn test_function() -> ComputeFunction {
+    ComputeFunction::Two(Box::new(TwoArgFunction::Add(
+        ComputeFunction::One(Box::new(SingleArgFunction::Sin(ComputeFunction::Zero(
+            Box::new(ConstantFunction::Coord(0)),
+        )))),
+        ComputeFunction::One(Box::new(SingleArgFunction::Loge(ComputeFunction::Zero(
+            Box::new(ConstantFunction::Constant(0.1, 0.2, 0.3)),
+        )))),
+    )))
+}
@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+
+use enum_methods::EnumMethods;
+use serde::{Deserialize, Serialize};
+
+use super::ComputeFunction;
+
+/// Address of a node in a tree, as the argument indices followed from the root
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize)]
+pub struct NodePath(pub Vec<usize>);
+
+impl NodePath {
+    pub fn root() -> Self {
+        Self(vec![])
+    }
+
+    /// Path to argument `index` of the node at self
+    pub fn child(&self, index: usize) -> Self {
+        let mut indices = self.0.clone();
+        indices.push(index);
+        Self(indices)
+    }
+
+    /// Path to the node holding self, or `None` for the root
+    pub fn parent(&self) -> Option<Self> {
+        let (_, parent) = self.0.split_last()?;
+        Some(Self(parent.to_vec()))
+    }
+
+    /// Level of the addressed node, where the root has depth 1 like `ComputeFunction::depth`
+    pub fn depth(&self) -> u32 {
+        self.0.len() as u32 + 1
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether the node at `other` lies in the subtree addressed by self
+    pub fn contains(&self, other: &NodePath) -> bool {
+        other.0.starts_with(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraversalOrder {
+    BreadthFirst,
+    /// Pre-order, visiting a node before its arguments
+    DepthFirst,
+}
+
+impl ComputeFunction {
+    /// Arguments of the root node, in index order
+    pub fn children(&self) -> Vec<&Self> {
+        match self {
+            ComputeFunction::One(inner) => inner.args().collect(),
+            ComputeFunction::Two(inner) => inner.args().collect(),
+            ComputeFunction::Zero(_) | ComputeFunction::Placeholder => vec![],
+        }
+    }
+
+    pub fn children_mut(&mut self) -> Vec<&mut Self> {
+        match self {
+            ComputeFunction::One(inner) => inner.args_mut().collect(),
+            ComputeFunction::Two(inner) => inner.args_mut().collect(),
+            ComputeFunction::Zero(_) | ComputeFunction::Placeholder => vec![],
+        }
+    }
+
+    /// Returns the node at `path`, or `None` if the path leaves the tree
+    pub fn get(&self, path: &NodePath) -> Option<&Self> {
+        path.0
+            .iter()
+            .try_fold(self, |node, &index| node.children().into_iter().nth(index))
+    }
+
+    pub fn get_mut(&mut self, path: &NodePath) -> Option<&mut Self> {
+        path.0.iter().try_fold(self, |node, &index| {
+            node.children_mut().into_iter().nth(index)
+        })
+    }
+
+    /// Replaces the subtree at `path` with `new`, returning the removed subtree
+    pub fn replace_subtree(&mut self, path: &NodePath, new: Self) -> Option<Self> {
+        let node = self.get_mut(path)?;
+        Some(std::mem::replace(node, new))
+    }
+
+    /// Paths of every node in the tree, in the given order
+    pub fn paths(&self, order: TraversalOrder) -> Vec<NodePath> {
+        let mut paths = vec![];
+        match order {
+            TraversalOrder::BreadthFirst => {
+                let mut frontier = VecDeque::from([(self, NodePath::root())]);
+                while let Some((node, path)) = frontier.pop_front() {
+                    for (i, child) in node.children().into_iter().enumerate() {
+                        frontier.push_back((child, path.child(i)));
+                    }
+                    paths.push(path);
+                }
+            }
+            TraversalOrder::DepthFirst => {
+                let mut stack = vec![(self, NodePath::root())];
+                while let Some((node, path)) = stack.pop() {
+                    for (i, child) in node.children().into_iter().enumerate().rev() {
+                        stack.push((child, path.child(i)));
+                    }
+                    paths.push(path);
+                }
+            }
+        }
+        paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compute_functions::{test_function, ConstantFunction};
+
+    use super::*;
+
+    #[test]
+    fn test_paths() {
+        let function = test_function();
+        let bfs = function.paths(TraversalOrder::BreadthFirst);
+        let expected: Vec<NodePath> = [vec![], vec![0], vec![1], vec![0, 0], vec![1, 0]]
+            .into_iter()
+            .map(NodePath)
+            .collect();
+        assert_eq!(bfs, expected);
+        let dfs = function.paths(TraversalOrder::DepthFirst);
+        let expected: Vec<NodePath> = [vec![], vec![0], vec![0, 0], vec![1], vec![1, 0]]
+            .into_iter()
+            .map(NodePath)
+            .collect();
+        assert_eq!(dfs, expected);
+        assert!(dfs.iter().all(|path| function.get(path).is_some()));
+    }
+
+    #[test]
+    fn test_replace_subtree() {
+        let mut function = test_function();
+        let path = NodePath(vec![1, 0]);
+        let old = function
+            .replace_subtree(
+                &path,
+                ComputeFunction::Zero(Box::new(ConstantFunction::Coord(1))),
+            )
+            .unwrap();
+        assert!(
+            matches!(old, ComputeFunction::Zero(c) if matches!(*c, ConstantFunction::Constant(..)))
+        );
+        assert!(matches!(
+            function.get(&path),
+            Some(ComputeFunction::Zero(c)) if matches!(**c, ConstantFunction::Coord(1))
+        ));
+        assert!(function.get(&NodePath(vec![0, 0, 0])).is_none());
+        assert!(function
+            .replace_subtree(&NodePath(vec![2]), ComputeFunction::Placeholder)
+            .is_none());
+    }
+}
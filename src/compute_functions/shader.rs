@@ -1,110 +1,140 @@
+use enum_methods::EnumMethods;
 use log::trace;
 
-use super::{ComputeFunction, ConstantFunction, SingleArgFunction, TwoArgFunction};
+use super::{
+    shader_builder::{ConstantMode, Helper, ShaderBuilder},
+    ComputeFunction, ConstantFunction, SingleArgFunction, TwoArgFunction,
+};
 
 pub trait ShaderFunction {
-    /// Generates inner shader function code
-    fn inner_shader(&self) -> String;
-    /// Generate complete shader code
+    /// Adds the statements computing self to `builder`, returning a `vec3<f32>` expression for its value
+    fn emit(&self, builder: &mut ShaderBuilder) -> String;
+    /// Generate complete shader code, with constants inlined
     fn get_shader_code(&self) -> String {
-        let shader = include_str!("../shaders/compute_frame.wgsl")
-            .replace("0.123456789", &self.inner_shader())
-            .to_string();
+        self.get_shader_code_with(ConstantMode::Inline)
+    }
+    /// Generate complete shader code, compiling constants according to `constant_mode`
+    fn get_shader_code_with(&self, constant_mode: ConstantMode) -> String {
+        let mut builder = ShaderBuilder::frame(constant_mode);
+        let value = self.emit(&mut builder);
+        builder.write_rgb(&value);
+        let shader = builder.build();
         trace!("generated shader:\n{}", &shader);
         shader
     }
 }
 
 impl ShaderFunction for ComputeFunction {
-    fn inner_shader(&self) -> String {
+    fn emit(&self, builder: &mut ShaderBuilder) -> String {
         match self {
-            ComputeFunction::Zero(arg) => arg.inner_shader(),
-            ComputeFunction::One(arg) => arg.inner_shader(),
-            ComputeFunction::Two(arg) => arg.inner_shader(),
-            ComputeFunction::Placeholder => todo!(),
+            ComputeFunction::Zero(arg) => arg.emit(builder),
+            ComputeFunction::One(arg) => arg.emit(builder),
+            ComputeFunction::Two(arg) => arg.emit(builder),
+            // Placeholders have no value, so they emit an undeclared name that fails validation
+            ComputeFunction::Placeholder => "placeholder".to_string(),
         }
     }
 }
 
+/// Parses and validates wgsl with naga, returning the diagnostic on failure
+pub fn validate_shader(source: &str) -> Result<(), String> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| e.emit_to_string(source))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+    .validate(&module)
+    .map_err(|e| e.emit_to_string(source))?;
+    Ok(())
+}
+
 impl ShaderFunction for ConstantFunction {
-    fn inner_shader(&self) -> String {
+    /// Leaves are cheap, so they are returned inline rather than bound to a variable
+    fn emit(&self, builder: &mut ShaderBuilder) -> String {
         match self {
-            ConstantFunction::Constant(r, g, b) => format!("vec3({},{},{})", r, g, b),
+            ConstantFunction::Constant(r, g, b) => builder.constant([*r, *g, *b]),
             ConstantFunction::Coord(dim) => match dim {
-                0 => "vec3(x,x,x)",
-                1 => "vec3(y,y,y)",
-                _ => "vec3(z,z,z)",
+                0 => "vec3<f32>(x)",
+                1 => "vec3<f32>(y)",
+                _ => "vec3<f32>(z)",
             }
             .to_string(),
         }
     }
 }
 
-impl ShaderFunction for SingleArgFunction {
-    fn inner_shader(&self) -> String {
+impl SingleArgFunction {
+    /// Wgsl expression applying this operator to the `vec3<f32>` expression `a`, ignoring the stored argument
+    pub fn shader_expression(&self, builder: &mut ShaderBuilder, a: &str) -> String {
         match self {
-            SingleArgFunction::Sin(arg) => format!("sin({})", arg.inner_shader()),
-            SingleArgFunction::Cos(arg) => format!("cos({})", arg.inner_shader()),
-            SingleArgFunction::Tan(arg) => format!("tan({})", arg.inner_shader()),
-            SingleArgFunction::Atan(arg) => format!("atan({})", arg.inner_shader()),
-            SingleArgFunction::Sinh(arg) => format!("sinh({})", arg.inner_shader()),
-            SingleArgFunction::Cosh(arg) => format!("cosh({})", arg.inner_shader()),
-            SingleArgFunction::Abs(arg) => format!("abs({})", arg.inner_shader()),
-            SingleArgFunction::Reciprocal(arg) => format!("1/({})", arg.inner_shader()),
-            SingleArgFunction::Square(arg) => format!("pow({},2)", arg.inner_shader()),
-            SingleArgFunction::SquareRoot(arg) => format!("sqrt({})", arg.inner_shader()),
-            SingleArgFunction::Loge(arg) => {
-                format!("log({})", arg.inner_shader())
+            SingleArgFunction::Sin(_) => format!("sin({})", a),
+            SingleArgFunction::Cos(_) => format!("cos({})", a),
+            SingleArgFunction::Tan(_) => format!("tan({})", a),
+            SingleArgFunction::Atan(_) => format!("atan({})", a),
+            SingleArgFunction::Sinh(_) => format!("sinh({})", a),
+            SingleArgFunction::Cosh(_) => format!("cosh({})", a),
+            SingleArgFunction::Abs(_) => format!("abs({})", a),
+            SingleArgFunction::Reciprocal(_) => format!("(1.0/{})", a),
+            SingleArgFunction::Square(_) => {
+                format!("{}({})", builder.use_helper(Helper::Square), a)
             }
+            SingleArgFunction::SquareRoot(_) => format!("sqrt({})", a),
+            SingleArgFunction::Loge(_) => format!("log({})", a),
         }
     }
 }
 
-impl ShaderFunction for TwoArgFunction {
-    fn inner_shader(&self) -> String {
+impl ShaderFunction for SingleArgFunction {
+    fn emit(&self, builder: &mut ShaderBuilder) -> String {
+        let a = self.get_arg(0usize).emit(builder);
+        let expression = self.shader_expression(builder, &a);
+        builder.push_let(&expression)
+    }
+}
+
+impl TwoArgFunction {
+    /// Wgsl expression applying this operator to the `vec3<f32>` expressions `a` and `b`, ignoring the stored
+    /// arguments
+    pub fn shader_expression(&self, builder: &mut ShaderBuilder, a: &str, b: &str) -> String {
+        let mut helper = |helper| format!("{}({},{})", builder.use_helper(helper), a, b);
         match self {
-            TwoArgFunction::Add(arg1, arg2) => {
-                format!("({}+{})", arg1.inner_shader(), arg2.inner_shader())
-            }
-            TwoArgFunction::Subtract(arg1, arg2) => {
-                format!("({}-{})", arg1.inner_shader(), arg2.inner_shader())
-            }
-            TwoArgFunction::Multiply(arg1, arg2) => {
-                format!("({}*{})", arg1.inner_shader(), arg2.inner_shader())
-            }
-            TwoArgFunction::Divide(arg1, arg2) => {
-                format!("({}/{})", arg1.inner_shader(), arg2.inner_shader())
-            }
-            TwoArgFunction::Min(arg1, arg2) => {
-                format!("min({},{})", arg1.inner_shader(), arg2.inner_shader())
-            }
-            TwoArgFunction::Max(arg1, arg2) => {
-                format!("max({},{})", arg1.inner_shader(), arg2.inner_shader())
-            }
-            TwoArgFunction::Avg(arg1, arg2) => {
-                format!("({}+{})/2", arg1.inner_shader(), arg2.inner_shader())
-            }
-            TwoArgFunction::Mod(arg1, arg2) => {
-                format!("({}%{})", arg1.inner_shader(), arg2.inner_shader())
-            }
-            TwoArgFunction::Exponent(arg1, arg2) => {
-                format!("pow({},{})", arg1.inner_shader(), arg2.inner_shader())
-            }
-            TwoArgFunction::And(arg1, arg2) => {
-                format!("({}&{})", arg1.inner_shader(), arg2.inner_shader())
-            }
-            TwoArgFunction::Or(arg1, arg2) => {
-                format!("({}|{})", arg1.inner_shader(), arg2.inner_shader())
-            }
-            TwoArgFunction::Xor(arg1, arg2) => {
-                format!("({}^{})", arg1.inner_shader(), arg2.inner_shader())
-            }
+            TwoArgFunction::Add(..) => format!("({}+{})", a, b),
+            TwoArgFunction::Subtract(..) => format!("({}-{})", a, b),
+            TwoArgFunction::Multiply(..) => format!("({}*{})", a, b),
+            TwoArgFunction::Divide(..) => format!("({}/{})", a, b),
+            TwoArgFunction::Min(..) => format!("min({},{})", a, b),
+            TwoArgFunction::Max(..) => format!("max({},{})", a, b),
+            TwoArgFunction::Avg(..) => format!("(({}+{})/2.0)", a, b),
+            TwoArgFunction::Mod(..) => format!("({}%{})", a, b),
+            TwoArgFunction::Exponent(..) => helper(Helper::Power),
+            TwoArgFunction::And(..) => helper(Helper::BitAnd),
+            TwoArgFunction::Or(..) => helper(Helper::BitOr),
+            TwoArgFunction::Xor(..) => helper(Helper::BitXor),
         }
     }
 }
 
+impl ShaderFunction for TwoArgFunction {
+    fn emit(&self, builder: &mut ShaderBuilder) -> String {
+        let a = self.get_arg(0usize).emit(builder);
+        let b = self.get_arg(1usize).emit(builder);
+        let expression = self.shader_expression(builder, &a, &b);
+        builder.push_let(&expression)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use rand::SeedableRng;
+
+    use crate::{
+        compute_functions::{
+            test_utils::{every_operator, leaf},
+            utils::GenerationMethod,
+        },
+        gene::GeneRng,
+    };
+
     use super::*;
 
     #[test]
@@ -113,5 +143,66 @@ mod tests {
             SingleArgFunction::Sin(ComputeFunction::Zero(Box::new(ConstantFunction::Coord(0))));
         let result = compute_function.get_shader_code();
         println!("{}", result);
+        assert!(result.contains("let n0: vec3<f32> = sin(vec3<f32>(x));"));
+        assert!(result.contains("result[index + 2u] = n0[2];"));
+        assert!(!result.contains("fn square"));
+    }
+
+    #[test]
+    fn test_literal_is_not_spliced() {
+        let function: ComputeFunction = "x + rgb(0.123456789, 0.123456789, 0.123456789)"
+            .parse()
+            .unwrap();
+        let shader = function.get_shader_code();
+        assert!(shader.contains("0.12345679"));
+        validate(&function);
+    }
+
+    fn validate(function: &ComputeFunction) {
+        for mode in [ConstantMode::Inline, ConstantMode::Buffer] {
+            validate_shader(&function.get_shader_code_with(mode))
+                .unwrap_or_else(|e| panic!("{} {:?}: {}", function, mode, e));
+        }
+    }
+
+    #[test]
+    fn test_invalid_shader_is_reported() {
+        let diagnostic = validate_shader("fn main() { let a: f32 = vec3<f32>(1.0); }").unwrap_err();
+        assert!(diagnostic.contains("vec3"), "{}", diagnostic);
+    }
+
+    #[test]
+    fn test_every_operator_validates() {
+        for function in every_operator([1.0, -2.5, 1e-7]) {
+            validate(&function);
+        }
+        for constant in [
+            ConstantFunction::Constant(f32::NAN, f32::INFINITY, f32::NEG_INFINITY),
+            ConstantFunction::Constant(f32::MAX, f32::MIN_POSITIVE, -0.0),
+            ConstantFunction::Coord(2),
+            ConstantFunction::Coord(7),
+        ] {
+            validate(&leaf(constant));
+        }
+    }
+
+    #[test]
+    fn test_buffered_constants() {
+        let function: ComputeFunction = "rgb(1, 2, 3) * x + rgb(4, 5, 6)".parse().unwrap();
+        let shader = function.get_shader_code_with(ConstantMode::Buffer);
+        assert!(shader.contains("var<storage> constants: array<vec4<f32>>;"));
+        assert!(shader.contains("(constants[0].xyz*vec3<f32>(x))"));
+        assert!(shader.contains("constants[1].xyz"));
+        assert!(!shader.contains("vec3<f32>(1.0"));
+    }
+
+    #[test]
+    fn test_random_trees_validate() {
+        let mut rng = GeneRng::seed_from_u64(0);
+        for _ in 0..50 {
+            let function =
+                ComputeFunction::random_deep(GenerationMethod::Grow, 1, 6, &mut rng).unwrap();
+            validate(&function);
+        }
     }
 }
@@ -0,0 +1,373 @@
+use std::fmt::Write;
+
+use super::evaluate::Rgb;
+
+/// Wgsl functions that generated code can call, included in a shader only when used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Helper {
+    Square,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+}
+
+impl Helper {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Helper::Square => "square",
+            Helper::Power => "power",
+            Helper::BitAnd => "bit_and",
+            Helper::BitOr => "bit_or",
+            Helper::BitXor => "bit_xor",
+        }
+    }
+
+    /// Bit patterns of the non-finite floats the helper reads, see `ShaderBuilder::float`
+    fn float_bits(&self) -> &'static [u32] {
+        match self {
+            Helper::Power => &[0x7fc00000],
+            _ => &[],
+        }
+    }
+
+    fn source(&self) -> &'static str {
+        match self {
+            Helper::Square => {
+                "fn square(v: vec3<f32>) -> vec3<f32> {\n    return v * v;\n}\n"
+            }
+            // Wgsl leaves `pow` undefined for negative bases, so make it NaN like the cpu reference
+            Helper::Power => {
+                "fn power(a: vec3<f32>, b: vec3<f32>) -> vec3<f32> {\n    return select(pow(a, b), vec3<f32>(bitcast<f32>(bits_7fc00000)), a < vec3<f32>(0.0));\n}\n"
+            }
+            // Bitwise operators act on the raw bits of each channel
+            Helper::BitAnd => {
+                "fn bit_and(a: vec3<f32>, b: vec3<f32>) -> vec3<f32> {\n    return bitcast<vec3<f32>>(bitcast<vec3<u32>>(a) & bitcast<vec3<u32>>(b));\n}\n"
+            }
+            Helper::BitOr => {
+                "fn bit_or(a: vec3<f32>, b: vec3<f32>) -> vec3<f32> {\n    return bitcast<vec3<f32>>(bitcast<vec3<u32>>(a) | bitcast<vec3<u32>>(b));\n}\n"
+            }
+            Helper::BitXor => {
+                "fn bit_xor(a: vec3<f32>, b: vec3<f32>) -> vec3<f32> {\n    return bitcast<vec3<f32>>(bitcast<vec3<u32>>(a) ^ bitcast<vec3<u32>>(b));\n}\n"
+            }
+        }
+    }
+}
+
+/// Workgroup size of frame shaders, which are dispatched as a 2d grid with one invocation per pixel
+pub const FRAME_WORKGROUP_SIZE: [u32; 3] = [8, 8, 1];
+
+/// Binding of the constants array in `ConstantMode::Buffer` frame shaders
+pub const CONSTANTS_BINDING: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How constant leaves are compiled
+pub enum ConstantMode {
+    /// Baked into the shader as literals
+    #[default]
+    Inline,
+    /// Read by index from a `constants` storage array, so they can change without recompiling
+    ///
+    /// Each constant is a `vec4<f32>` with the value in `xyz`, see `pack_constants`
+    Buffer,
+}
+
+/// Lays out constants for the `constants` array of `ConstantMode::Buffer` shaders
+///
+/// Always holds at least one element, as bindings cannot be empty
+pub fn pack_constants(values: &[Rgb]) -> Vec<f32> {
+    let mut packed: Vec<f32> = values
+        .iter()
+        .flat_map(|[r, g, b]| [*r, *g, *b, 0.0])
+        .collect();
+    if packed.is_empty() {
+        packed.resize(4, 0.0);
+    }
+    packed
+}
+
+/// Name of the private variable holding the bits of a non-finite float
+fn float_bits_name(bits: u32) -> String {
+    format!("bits_{:08x}", bits)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    Storage,
+    StorageReadWrite,
+    Uniform,
+}
+
+impl AddressSpace {
+    fn declaration(&self) -> &'static str {
+        match self {
+            AddressSpace::Storage => "var<storage>",
+            AddressSpace::StorageReadWrite => "var<storage, read_write>",
+            AddressSpace::Uniform => "var<uniform>",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub name: String,
+    pub space: AddressSpace,
+    pub ty: String,
+}
+
+#[derive(Debug, Clone)]
+/// Assembles a compute shader from separate sections
+///
+/// Sections are written in order: structs, bindings (all in group 0, numbered by insertion order), private
+/// variables, helpers,
+/// then the entry point made of the preamble, per-node `let` statements and output writes
+pub struct ShaderBuilder {
+    structs: Vec<String>,
+    bindings: Vec<Binding>,
+    helpers: Vec<Helper>,
+    /// Bit patterns of non-finite floats used, each declared as a private variable
+    float_bits: Vec<u32>,
+    workgroup_size: [u32; 3],
+    constant_mode: ConstantMode,
+    constant_count: usize,
+    preamble: Vec<String>,
+    statements: Vec<String>,
+    outputs: Vec<String>,
+}
+
+impl ShaderBuilder {
+    pub fn new(workgroup_size: [u32; 3]) -> Self {
+        Self {
+            structs: vec![],
+            bindings: vec![],
+            helpers: vec![],
+            float_bits: vec![],
+            workgroup_size,
+            constant_mode: ConstantMode::Inline,
+            constant_count: 0,
+            preamble: vec![],
+            statements: vec![],
+            outputs: vec![],
+        }
+    }
+
+    /// Builder for rendering one frame into `result`, with `x`, `y`, `z` and `index` available to statements
+    ///
+    /// Binds `result`, `resolution` and `bounds` at 0, 1 and 2, and `constants` at `CONSTANTS_BINDING` in
+    /// `ConstantMode::Buffer`
+    pub fn frame(constant_mode: ConstantMode) -> Self {
+        let mut builder = Self::new(FRAME_WORKGROUP_SIZE);
+        builder.add_struct("struct Resolution {\n  x: u32,\n  y: u32\n}\n");
+        builder.add_struct(
+            "struct Bounds {\n  x: f32,\n  y: f32,\n  z: f32,\n  w: f32,\n  h: f32\n}\n",
+        );
+        builder.add_binding("result", AddressSpace::StorageReadWrite, "array<f32>");
+        builder.add_binding("resolution", AddressSpace::Storage, "Resolution");
+        builder.add_binding("bounds", AddressSpace::Storage, "Bounds");
+        if constant_mode == ConstantMode::Buffer {
+            builder.add_binding("constants", AddressSpace::Storage, "array<vec4<f32>>");
+        }
+        builder.constant_mode = constant_mode;
+        for statement in [
+            "if global_id.x >= resolution.x || global_id.y >= resolution.y {\n        return;\n    }",
+            "let x: f32 = f32(global_id.x) / f32(resolution.x) * bounds.w + bounds.x;",
+            "let y: f32 = f32(global_id.y) / f32(resolution.y) * bounds.h + bounds.y;",
+            "let z: f32 = bounds.z;",
+            "let index: u32 = (global_id.y * resolution.x + global_id.x) * 3u;",
+        ] {
+            builder.add_preamble(statement);
+        }
+        builder
+    }
+
+    pub fn add_struct(&mut self, source: &str) {
+        self.structs.push(source.to_string());
+    }
+
+    /// Declares a variable in group 0, returning its binding number
+    pub fn add_binding(&mut self, name: &str, space: AddressSpace, ty: &str) -> u32 {
+        self.bindings.push(Binding {
+            name: name.to_string(),
+            space,
+            ty: ty.to_string(),
+        });
+        self.bindings.len() as u32 - 1
+    }
+
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    /// Ensures `helper` is defined, returning the name to call it by
+    pub fn use_helper(&mut self, helper: Helper) -> &'static str {
+        if !self.helpers.contains(&helper) {
+            self.helpers.push(helper);
+            for bits in helper.float_bits() {
+                self.use_float_bits(*bits);
+            }
+        }
+        helper.name()
+    }
+
+    fn use_float_bits(&mut self, bits: u32) -> String {
+        if !self.float_bits.contains(&bits) {
+            self.float_bits.push(bits);
+        }
+        float_bits_name(bits)
+    }
+
+    /// Formats `value` as a wgsl expression of type `f32`
+    ///
+    /// Wgsl has no literals for NaN or infinity, and constant expressions evaluating to them are a shader-creation
+    /// error, so those are reinterpreted at runtime from the bits in a private variable
+    pub fn float(&mut self, value: f32) -> String {
+        if value.is_finite() {
+            // Debug formatting always includes a decimal point or exponent, so it is never read as an integer
+            format!("{:?}", value)
+        } else {
+            format!("bitcast<f32>({})", self.use_float_bits(value.to_bits()))
+        }
+    }
+
+    /// Returns a `vec3<f32>` expression for a constant, according to the builder's `ConstantMode`
+    pub fn constant(&mut self, [r, g, b]: Rgb) -> String {
+        match self.constant_mode {
+            ConstantMode::Inline => format!(
+                "vec3<f32>({},{},{})",
+                self.float(r),
+                self.float(g),
+                self.float(b)
+            ),
+            ConstantMode::Buffer => {
+                self.constant_count += 1;
+                format!("constants[{}].xyz", self.constant_count - 1)
+            }
+        }
+    }
+
+    /// Adds a statement to run before any node is computed
+    pub fn add_preamble(&mut self, statement: &str) {
+        self.preamble.push(statement.to_string());
+    }
+
+    /// Adds a statement to the entry point after the preamble
+    pub fn add_statement(&mut self, statement: &str) {
+        self.statements.push(statement.to_string());
+    }
+
+    /// Binds `expression` to a new variable, returning the variable name
+    pub fn push_let(&mut self, expression: &str) -> String {
+        let name = format!("n{}", self.statements.len());
+        self.statements
+            .push(format!("let {}: vec3<f32> = {};", name, expression));
+        name
+    }
+
+    pub fn add_output(&mut self, statement: &str) {
+        self.outputs.push(statement.to_string());
+    }
+
+    /// Writes an rgb value to the frame's `result` buffer at `index`
+    pub fn write_rgb(&mut self, value: &str) {
+        for channel in 0..3 {
+            self.add_output(&format!(
+                "result[index + {}u] = {}[{}];",
+                channel, value, channel
+            ));
+        }
+    }
+
+    pub fn build(&self) -> String {
+        let mut shader = String::new();
+        for source in &self.structs {
+            writeln!(shader, "{}", source).unwrap();
+        }
+        for (i, binding) in self.bindings.iter().enumerate() {
+            writeln!(
+                shader,
+                "@group(0)\n@binding({})\n{} {}: {};\n",
+                i,
+                binding.space.declaration(),
+                binding.name,
+                binding.ty
+            )
+            .unwrap();
+        }
+        for bits in &self.float_bits {
+            writeln!(
+                shader,
+                "var<private> {}: u32 = {:#x}u;\n",
+                float_bits_name(*bits),
+                bits
+            )
+            .unwrap();
+        }
+        for helper in &self.helpers {
+            writeln!(shader, "{}", helper.source()).unwrap();
+        }
+        let [wx, wy, wz] = self.workgroup_size;
+        writeln!(
+            shader,
+            "@compute\n@workgroup_size({},{},{})\nfn main(@builtin(global_invocation_id) global_id: vec3<u32>) {{",
+            wx, wy, wz
+        )
+        .unwrap();
+        for statement in self
+            .preamble
+            .iter()
+            .chain(&self.statements)
+            .chain(&self.outputs)
+        {
+            writeln!(shader, "    {}", statement).unwrap();
+        }
+        writeln!(shader, "}}").unwrap();
+        shader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compute_functions::shader::validate_shader;
+
+    use super::*;
+
+    #[test]
+    fn test_sections() {
+        let mut builder = ShaderBuilder::frame(ConstantMode::Inline);
+        let constants = builder.add_binding("constants", AddressSpace::Uniform, "vec4<f32>");
+        assert_eq!(constants, 3);
+        let square = builder.use_helper(Helper::Square);
+        builder.use_helper(Helper::Square);
+        let value = builder.push_let(&format!("{}(vec3<f32>(x))", square));
+        builder.write_rgb(&value);
+        let shader = builder.build();
+        assert_eq!(shader.matches("fn square").count(), 1);
+        assert!(!shader.contains("fn power"));
+        assert!(shader.contains("@binding(3)\nvar<uniform> constants: vec4<f32>;"));
+        assert!(shader.contains("let n0: vec3<f32> = square(vec3<f32>(x));"));
+        validate_shader(&shader).unwrap();
+    }
+
+    #[test]
+    fn test_non_finite_floats_are_read_at_runtime() {
+        let mut builder = ShaderBuilder::frame(ConstantMode::Inline);
+        let nan = builder.constant([f32::NAN, f32::INFINITY, 1.5]);
+        let power = builder.use_helper(Helper::Power);
+        let value = builder.push_let(&format!("{}({},vec3<f32>(x))", power, nan));
+        builder.write_rgb(&value);
+        let shader = builder.build();
+        assert_eq!(
+            nan,
+            "vec3<f32>(bitcast<f32>(bits_7fc00000),bitcast<f32>(bits_7f800000),1.5)"
+        );
+        assert_eq!(
+            shader
+                .matches("var<private> bits_7fc00000: u32 = 0x7fc00000u;")
+                .count(),
+            1
+        );
+        assert!(shader.contains("var<private> bits_7f800000: u32 = 0x7f800000u;"));
+        assert!(!shader.contains("bitcast<f32>(0x"));
+        validate_shader(&shader).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+use std::mem::take;
+
+use enum_methods::EnumMethods;
+
+use super::{
+    evaluate::{CpuFunction, Rgb},
+    ComputeFunction, ConstantFunction, SingleArgFunction, TwoArgFunction,
+};
+
+impl ComputeFunction {
+    /// Returns an equivalent, usually smaller tree
+    ///
+    /// Constant subtrees are folded, identity and annihilator rules are applied and double reciprocals removed.
+    /// The output matches the original up to float rounding wherever the original is finite
+    pub fn simplify(&self) -> Self {
+        self.clone().simplified()
+    }
+
+    fn simplified(mut self) -> Self {
+        for child in self.children_mut() {
+            *child = take(child).simplified();
+        }
+        let foldable = matches!(self, ComputeFunction::One(_) | ComputeFunction::Two(_))
+            && self
+                .children()
+                .iter()
+                .all(|child| child.constant().is_some());
+        if foldable {
+            let value = self.evaluate(0.0, 0.0, 0.0);
+            // Non-finite values are left for the gpu to compute, as they have no wgsl literal
+            if value.iter().all(|v| v.is_finite()) {
+                return constant(value);
+            }
+        }
+        match self {
+            ComputeFunction::One(inner) => simplify_one(*inner),
+            ComputeFunction::Two(inner) => simplify_two(*inner),
+            other => other,
+        }
+    }
+
+    fn constant(&self) -> Option<Rgb> {
+        match self {
+            ComputeFunction::Zero(inner) => match **inner {
+                ConstantFunction::Constant(r, g, b) => Some([r, g, b]),
+                ConstantFunction::Coord(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether self is a constant with every channel equal to `value`
+    fn is_value(&self, value: f32) -> bool {
+        self.constant()
+            .is_some_and(|c| c.iter().all(|v| *v == value))
+    }
+}
+
+fn constant([r, g, b]: Rgb) -> ComputeFunction {
+    ComputeFunction::Zero(Box::new(ConstantFunction::Constant(r, g, b)))
+}
+
+fn simplify_one(function: SingleArgFunction) -> ComputeFunction {
+    match function {
+        // recip(recip(a)) = a
+        SingleArgFunction::Reciprocal(ComputeFunction::One(mut inner))
+            if matches!(*inner, SingleArgFunction::Reciprocal(_)) =>
+        {
+            take(inner.get_arg_mut(0usize))
+        }
+        // abs(abs(a)) = abs(a) and abs(sq(a)) = sq(a)
+        SingleArgFunction::Abs(arg)
+            if matches!(
+                &arg,
+                ComputeFunction::One(inner)
+                    if matches!(**inner, SingleArgFunction::Abs(_) | SingleArgFunction::Square(_))
+            ) =>
+        {
+            arg
+        }
+        function => ComputeFunction::One(Box::new(function)),
+    }
+}
+
+fn simplify_two(function: TwoArgFunction) -> ComputeFunction {
+    let zero = || constant([0.0; 3]);
+    match function {
+        TwoArgFunction::Add(a, b) if b.is_value(0.0) => a,
+        TwoArgFunction::Add(a, b) if a.is_value(0.0) => b,
+        TwoArgFunction::Subtract(a, b) if b.is_value(0.0) => a,
+        TwoArgFunction::Subtract(a, b) if a == b => zero(),
+        TwoArgFunction::Multiply(a, b) if b.is_value(1.0) => a,
+        TwoArgFunction::Multiply(a, b) if a.is_value(1.0) => b,
+        TwoArgFunction::Multiply(a, b) if a.is_value(0.0) || b.is_value(0.0) => zero(),
+        TwoArgFunction::Divide(a, b) if b.is_value(1.0) => a,
+        TwoArgFunction::Divide(a, _) if a.is_value(0.0) => zero(),
+        TwoArgFunction::Min(a, b)
+        | TwoArgFunction::Max(a, b)
+        | TwoArgFunction::Avg(a, b)
+        | TwoArgFunction::And(a, b)
+        | TwoArgFunction::Or(a, b)
+            if a == b =>
+        {
+            a
+        }
+        TwoArgFunction::Xor(a, b) if a == b => zero(),
+        function => ComputeFunction::Two(Box::new(function)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use crate::{compute_functions::utils::GenerationMethod, gene::GeneRng};
+
+    use super::*;
+
+    fn parse(s: &str) -> ComputeFunction {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_rules() {
+        let cases = [
+            ("sin(rgb(0, 0, 0)) + x", "x"),
+            ("x * rgb(1, 1, 1) - rgb(0, 0, 0)", "x"),
+            (
+                "(y + sin(x)) * (rgb(1, 2, 3) - rgb(1, 2, 3))",
+                "rgb(0, 0, 0)",
+            ),
+            ("recip(recip(cos(x)))", "cos(x)"),
+            ("abs(abs(sq(y)))", "sq(y)"),
+            ("xor(x / rgb(1, 1, 1), x)", "rgb(0, 0, 0)"),
+            ("max(sin(x), sin(x)) - sin(x)", "rgb(0, 0, 0)"),
+            ("x * rgb(1, 2, 1)", "x * rgb(1, 2, 1)"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).simplify().to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_preserves_output() {
+        let mut rng = GeneRng::seed_from_u64(0);
+        let (mut before, mut after) = (0, 0);
+        for _ in 0..200 {
+            let function =
+                ComputeFunction::random_deep(GenerationMethod::Grow, 1, 6, &mut rng).unwrap();
+            let simplified = function.simplify();
+            before += function.bfs().len();
+            after += simplified.bfs().len();
+            for i in 0..25 {
+                let (x, y, z) = ((i % 5) as f32 * 0.4 - 1.0, (i / 5) as f32 * 0.4 - 1.0, 0.3);
+                let original = function.evaluate(x, y, z);
+                let result = simplified.evaluate(x, y, z);
+                for (a, b) in original.iter().zip(result) {
+                    if a.is_finite() {
+                        assert!(
+                            (a - b).abs() <= 1e-4 * a.abs().max(1.0),
+                            "{} became {}: {} != {}",
+                            function,
+                            simplified,
+                            a,
+                            b
+                        );
+                    }
+                }
+            }
+        }
+        assert!(after < before);
+    }
+}
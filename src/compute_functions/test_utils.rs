@@ -0,0 +1,42 @@
+use std::fmt::Display;
+
+use enum_methods::EnumMethods;
+use strum::IntoEnumIterator;
+
+use super::{ComputeFunction, ConstantFunction, SingleArgFunction, TwoArgFunction};
+
+pub(crate) fn leaf(constant: ConstantFunction) -> ComputeFunction {
+    ComputeFunction::Zero(Box::new(constant))
+}
+
+/// One tree per operator, taking x as the single argument, or y and `constant` as the two arguments
+pub(crate) fn every_operator(constant: [f32; 3]) -> Vec<ComputeFunction> {
+    let mut functions = vec![];
+    for mut function in SingleArgFunction::iter() {
+        function.set_arg(0usize, leaf(ConstantFunction::Coord(0)));
+        functions.push(ComputeFunction::One(Box::new(function)));
+    }
+    for mut function in TwoArgFunction::iter() {
+        let [r, g, b] = constant;
+        function.set_arg(0usize, leaf(ConstantFunction::Coord(1)));
+        function.set_arg(1usize, leaf(ConstantFunction::Constant(r, g, b)));
+        functions.push(ComputeFunction::Two(Box::new(function)));
+    }
+    functions
+}
+
+/// Asserts the buffers match to within `tolerance` relative to `expected`, with NaN matching NaN
+pub(crate) fn assert_close(
+    function: &impl Display,
+    actual: &[f32],
+    expected: &[f32],
+    tolerance: f32,
+) {
+    assert_eq!(actual.len(), expected.len(), "{}", function);
+    for (actual, expected) in actual.iter().zip(expected) {
+        let matches = (actual.is_nan() && expected.is_nan())
+            || actual == expected
+            || (actual - expected).abs() <= tolerance * expected.abs().max(1.0);
+        assert!(matches, "{}: {} != {}", function, actual, expected);
+    }
+}
@@ -5,7 +5,7 @@ use strum::IntoEnumIterator;
 
 use rand::{
     distributions::{Distribution, Standard, WeightedIndex},
-    thread_rng, Rng,
+    Rng,
 };
 
 use crate::{
@@ -13,7 +13,29 @@ use crate::{
     error::ApplicationError,
 };
 
-use super::ComputeFunction;
+use super::{evaluate::Rgb, ComputeFunction};
+
+/// Tree initialisation strategy used by `ComputeFunction::random_deep`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenerationMethod {
+    /// Any node may be a leaf once `min_depth` is reached
+    Grow,
+    /// Every branch is extended to exactly `max_depth`
+    Full,
+}
+
+impl GenerationMethod {
+    /// Method and maximum depth of tree `index` under ramped half-and-half initialisation
+    pub fn ramped(index: usize, min_depth: u32, max_depth: u32) -> (Self, u32) {
+        let method = if index.is_multiple_of(2) {
+            GenerationMethod::Grow
+        } else {
+            GenerationMethod::Full
+        };
+        let depths = (max_depth.saturating_sub(min_depth) + 1) as usize;
+        (method, min_depth + ((index / 2) % depths) as u32)
+    }
+}
 
 impl ComputeFunction {
     /// BFS search through self as root
@@ -44,16 +66,18 @@ impl ComputeFunction {
     /// # Arguments
     ///
     /// * `arg_weights` - Probability weights for returned fucnction having 0, 1 or 2} arguments
-    pub fn random(arg_weights: &[f32; 3]) -> Result<Self, ApplicationError> {
+    pub fn random<R: Rng + ?Sized>(
+        arg_weights: &[f32; 3],
+        rng: &mut R,
+    ) -> Result<Self, ApplicationError> {
         let arg_indices = [0, 1, 2];
         let dist = WeightedIndex::new(arg_weights).map_err(|_| ApplicationError::BadArg)?;
-        let mut rng = thread_rng();
-        let arg_count = arg_indices[dist.sample(&mut rng)];
+        let arg_count = arg_indices[dist.sample(rng)];
         let functions = match arg_count {
             0 => {
-                let v0: f32 = Standard.sample(&mut rng);
-                let v1: f32 = Standard.sample(&mut rng);
-                let v2: f32 = Standard.sample(&mut rng);
+                let v0: f32 = Standard.sample(rng);
+                let v1: f32 = Standard.sample(rng);
+                let v2: f32 = Standard.sample(rng);
                 let dim: u8 = rng.gen_range(0..3);
                 vec![
                     ComputeFunction::Zero(Box::new(ConstantFunction::Constant(v0, v1, v2))),
@@ -67,23 +91,165 @@ impl ComputeFunction {
                 .map(|x| ComputeFunction::Two(Box::new(x)))
                 .collect(),
         };
-        let func = functions.choose(&mut rng).ok_or(ApplicationError::BadArg)?;
+        let func = functions.choose(rng).ok_or(ApplicationError::BadArg)?;
         Ok(func.to_owned())
     }
 
-    pub fn random_deep(depth: u32) -> Result<Self, ApplicationError> {
-        let weight = (depth - 1) as f32;
-        let function = Self::random(&[1.0, weight, weight])?;
-        Ok(match function {
-            ComputeFunction::One(f) => todo!(),
-            ComputeFunction::Two(f) => todo!(),
-            s => s,
-        })
+    /// Returns a random tree whose depth lies between `min_depth` and `max_depth` inclusive, where a lone leaf has depth 1
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Whether branches may stop early (grow) or must all reach `max_depth` (full)
+    pub fn random_deep<R: Rng + ?Sized>(
+        method: GenerationMethod,
+        min_depth: u32,
+        max_depth: u32,
+        rng: &mut R,
+    ) -> Result<Self, ApplicationError> {
+        if min_depth == 0 || min_depth > max_depth {
+            return Err(ApplicationError::BadArg);
+        }
+        Self::random_deep_from(method, min_depth, max_depth, 1, rng)
+    }
+
+    fn random_deep_from<R: Rng + ?Sized>(
+        method: GenerationMethod,
+        min_depth: u32,
+        max_depth: u32,
+        depth: u32,
+        rng: &mut R,
+    ) -> Result<Self, ApplicationError> {
+        let arg_weights = if depth >= max_depth {
+            [1.0, 0.0, 0.0]
+        } else if depth < min_depth || method == GenerationMethod::Full {
+            [0.0, 1.0, 1.0]
+        } else {
+            [1.0, 1.0, 1.0]
+        };
+        let mut function = Self::random(&arg_weights, rng)?;
+        for arg in function.children_mut() {
+            *arg = Self::random_deep_from(method, min_depth, max_depth, depth + 1, rng)?;
+        }
+        Ok(function)
     }
+
+    /// Returns `count` random trees using ramped half-and-half initialisation
+    ///
+    /// Maximum depths are spread evenly over `min_depth..=max_depth`, with half of each depth generated by grow and half by full
+    pub fn ramped_half_and_half<R: Rng + ?Sized>(
+        count: usize,
+        min_depth: u32,
+        max_depth: u32,
+        rng: &mut R,
+    ) -> Result<Vec<Self>, ApplicationError> {
+        if min_depth == 0 || min_depth > max_depth {
+            return Err(ApplicationError::BadArg);
+        }
+        (0..count)
+            .map(|i| {
+                let (method, depth) = GenerationMethod::ramped(i, min_depth, max_depth);
+                Self::random_deep(method, min_depth, depth, rng)
+            })
+            .collect()
+    }
+
+    /// Number of levels in the tree, where a lone leaf has depth 1
+    pub fn depth(&self) -> u32 {
+        match self {
+            ComputeFunction::Zero(_) | ComputeFunction::Placeholder => 1,
+            ComputeFunction::One(inner) => 1 + inner.get_arg(0usize).depth(),
+            ComputeFunction::Two(inner) => {
+                1 + inner
+                    .get_arg(0usize)
+                    .depth()
+                    .max(inner.get_arg(1usize).depth())
+            }
+        }
+    }
+
+    /// Whether any node is still a placeholder, which no renderer can evaluate
+    pub fn has_placeholder(&self) -> bool {
+        match self {
+            ComputeFunction::Placeholder => true,
+            _ => self
+                .children()
+                .into_iter()
+                .any(|child| child.has_placeholder()),
+        }
+    }
+
+    /// Values of every constant leaf, left to right
+    ///
+    /// This is the order constants are emitted in by `ConstantMode::Buffer` shaders
+    pub fn constants(&self) -> Vec<Rgb> {
+        match self {
+            ComputeFunction::Zero(inner) => match **inner {
+                ConstantFunction::Constant(r, g, b) => vec![[r, g, b]],
+                ConstantFunction::Coord(_) => vec![],
+            },
+            _ => self
+                .children()
+                .into_iter()
+                .flat_map(|child| child.constants())
+                .collect(),
+        }
+    }
+
+    /// Overwrites constant leaves left to right with `values`, returning how many constants the tree has
+    pub fn set_constants(&mut self, values: &[Rgb]) -> usize {
+        let mut count = 0;
+        self.set_constants_from(values, &mut count);
+        count
+    }
+
+    fn set_constants_from(&mut self, values: &[Rgb], count: &mut usize) {
+        match self {
+            ComputeFunction::Zero(inner) => {
+                if let ConstantFunction::Constant(r, g, b) = &mut **inner {
+                    if let Some([vr, vg, vb]) = values.get(*count) {
+                        (*r, *g, *b) = (*vr, *vg, *vb);
+                    }
+                    *count += 1;
+                }
+            }
+            _ => {
+                for child in self.children_mut() {
+                    child.set_constants_from(values, count);
+                }
+            }
+        }
+    }
+
+    /// Copy with every constant zeroed, equal for trees that differ only in their constants
+    pub fn without_constants(&self) -> Self {
+        let mut skeleton = self.clone();
+        let zeros = vec![[0.0; 3]; skeleton.constants().len()];
+        skeleton.set_constants(&zeros);
+        skeleton
+    }
+
+    /// Hash of the tree's shape, operators and constants, equal for structurally identical trees
+    ///
+    /// Stable across runs, so it can key caches of compiled shaders
+    pub fn structural_hash(&self) -> u64 {
+        // Can unwrap because serialising to a vec cannot fail
+        fnv1a(&bincode::serialize(self).unwrap())
+    }
+}
+
+/// 64 bit FNV-1a hash
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
 }
 
 #[cfg(test)]
 mod tests {
+    use rand::SeedableRng;
+
+    use crate::gene::GeneRng;
+
     use crate::compute_functions::{ConstantFunction, SingleArgFunction, TwoArgFunction};
 
     use super::*;
@@ -111,7 +277,101 @@ mod tests {
 
     #[test]
     fn test_random() {
-        let random_func = ComputeFunction::random(&[1.0, 1.0, 1.0]).unwrap();
+        let random_func =
+            ComputeFunction::random(&[1.0, 1.0, 1.0], &mut GeneRng::seed_from_u64(0)).unwrap();
         println!("{:?}", &random_func);
     }
+
+    #[test]
+    fn test_enum_methods() {
+        let mut function = TwoArgFunction::Add(
+            ComputeFunction::Zero(Box::new(ConstantFunction::Coord(0))),
+            ComputeFunction::Zero(Box::new(ConstantFunction::Coord(1))),
+        );
+        assert_eq!(function.arity(), 2);
+        let old = function.set_arg(
+            1usize,
+            ComputeFunction::Zero(Box::new(ConstantFunction::Coord(2))),
+        );
+        assert!(
+            matches!(old, ComputeFunction::Zero(c) if matches!(*c, ConstantFunction::Coord(1)))
+        );
+        *function.get_arg_mut(0usize) = ComputeFunction::Placeholder;
+        let args: Vec<_> = function.args().collect();
+        assert!(matches!(args[0], ComputeFunction::Placeholder));
+        assert!(
+            matches!(args[1], ComputeFunction::Zero(c) if matches!(**c, ConstantFunction::Coord(2)))
+        );
+        assert_eq!(
+            SingleArgFunction::Sin(ComputeFunction::Placeholder).arity(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_random_deep() {
+        let mut rng = GeneRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let full =
+                ComputeFunction::random_deep(GenerationMethod::Full, 2, 4, &mut rng).unwrap();
+            assert_eq!(full.depth(), 4);
+            let grow =
+                ComputeFunction::random_deep(GenerationMethod::Grow, 2, 4, &mut rng).unwrap();
+            assert!((2..=4).contains(&grow.depth()));
+            assert!(grow
+                .bfs()
+                .iter()
+                .all(|(node, _)| !matches!(node, ComputeFunction::Placeholder)));
+        }
+        assert!(ComputeFunction::random_deep(GenerationMethod::Grow, 3, 2, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_ramped_half_and_half() {
+        let population =
+            ComputeFunction::ramped_half_and_half(12, 2, 4, &mut GeneRng::seed_from_u64(0))
+                .unwrap();
+        assert_eq!(population.len(), 12);
+        // Full trees land on every depth in the ramp
+        let mut full_depths: Vec<u32> = population
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(|f| f.depth())
+            .collect();
+        full_depths.sort();
+        assert_eq!(full_depths, vec![2, 2, 3, 3, 4, 4]);
+    }
+
+    #[test]
+    fn test_structural_hash() {
+        let parse = |s: &str| s.parse::<ComputeFunction>().unwrap();
+        assert_eq!(
+            parse("sin(x) + y").structural_hash(),
+            parse("sin(x) + y").structural_hash()
+        );
+        for other in [
+            "sin(y) + x",
+            "sin(x) - y",
+            "cos(x) + y",
+            "sin(x) + rgb(1, 2, 3)",
+        ] {
+            assert_ne!(
+                parse("sin(x) + y").structural_hash(),
+                parse(other).structural_hash()
+            );
+        }
+    }
+
+    #[test]
+    fn test_constants() {
+        let mut function: ComputeFunction = "sin(rgb(1, 2, 3)) + x * rgb(4, 5, 6)".parse().unwrap();
+        assert_eq!(function.constants(), vec![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        assert_eq!(
+            function.without_constants().to_string(),
+            "sin(rgb(0, 0, 0)) + x * rgb(0, 0, 0)"
+        );
+        assert_eq!(function.set_constants(&[[7.0, 8.0, 9.0]]), 2);
+        assert_eq!(function.to_string(), "sin(rgb(7, 8, 9)) + x * rgb(4, 5, 6)");
+    }
 }
@@ -0,0 +1,92 @@
+use crate::compute_functions::{evaluate::CpuFunction, image::ImageConfig};
+
+/// Renders `function` on the cpu into the same interleaved rgb layout as `GpuInstance::generate_buffer`
+pub fn generate_buffer(image_config: &ImageConfig, function: &dyn CpuFunction) -> Vec<f32> {
+    let mut result = Vec::with_capacity(image_config.pixels() as usize * 3);
+    for index in 0..image_config.pixels() {
+        let (x, y, z) = image_config.coordinates(index);
+        result.extend(function.evaluate(x, y, z));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::block_on;
+
+    use crate::{
+        compute_functions::{
+            image::{Bounds, Resolution},
+            test_utils::{assert_close, every_operator},
+            ComputeFunction, ConstantFunction, SingleArgFunction,
+        },
+        gene::Gene,
+        gpu::instance::GpuInstance,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_matches_gpu() {
+        let function =
+            SingleArgFunction::Sin(ComputeFunction::Zero(Box::new(ConstantFunction::Coord(0))));
+        let config = ImageConfig {
+            resolution: Resolution::new(10, 10),
+            bounds: Bounds::new(0.0, 0.0, 0.0, 1.0, 1.0),
+        };
+        let cpu_result = generate_buffer(&config, &function);
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        let gene = Gene::new(0, ComputeFunction::One(Box::new(function)));
+        let gpu_result = block_on(gpu.generate_buffer(&config, &gene)).unwrap();
+        assert_eq!(cpu_result.len(), gpu_result.len());
+        for (cpu, gpu) in cpu_result.iter().zip(&gpu_result) {
+            assert!((cpu - gpu).abs() < 1e-5, "cpu {} != gpu {}", cpu, gpu);
+        }
+    }
+
+    #[test]
+    fn test_every_operator_matches_gpu() {
+        let config = ImageConfig {
+            resolution: Resolution::new(8, 8),
+            bounds: Bounds::new(-2.0, -2.0, 0.5, 4.0, 4.0),
+        };
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        for function in every_operator([1.5, -0.5, 2.0]) {
+            let cpu_result = generate_buffer(&config, &function);
+            let gene = Gene::new(0, function.clone());
+            let gpu_result = block_on(gpu.generate_buffer(&config, &gene)).unwrap();
+            assert_close(&function, &gpu_result, &cpu_result, 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_non_square_coordinates_match_gpu() {
+        // Red and green hold the x and y coordinates of each pixel
+        let gene = Gene::new(0, "x * rgb(1, 0, 0) + y * rgb(0, 1, 0)".parse().unwrap());
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        for (width, height) in [(37, 5), (3, 41), (13, 7), (1, 1), (9, 8)] {
+            let config = ImageConfig {
+                resolution: Resolution::new(width, height),
+                bounds: Bounds::new(-1.0, 2.0, 0.0, 2.0, 3.0),
+            };
+            let cpu_result = generate_buffer(&config, gene.function());
+            let gpu_result = block_on(gpu.generate_buffer(&config, &gene)).unwrap();
+            assert_eq!(cpu_result.len(), (width * height * 3) as usize);
+            assert_eq!(cpu_result.len(), gpu_result.len());
+            for index in 0..config.pixels() {
+                let (x, y, _) = config.coordinates(index);
+                let pixel = &gpu_result[index as usize * 3..index as usize * 3 + 3];
+                assert!(
+                    (pixel[0] - x).abs() < 1e-5 && (pixel[1] - y).abs() < 1e-5,
+                    "{}x{} pixel {}: gpu {:?} != ({}, {})",
+                    width,
+                    height,
+                    index,
+                    pixel,
+                    x,
+                    y
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use enum_methods::EnumMethods;
+use rayon::prelude::*;
+
+use crate::{
+    compute_functions::{
+        evaluate::{bitwise, wgsl_pow, Rgb},
+        image::ImageConfig,
+        ComputeFunction, ConstantFunction, SingleArgFunction, TwoArgFunction,
+    },
+    error::RenderError,
+};
+
+/// Pixels evaluated together by each instruction, enough for loops over them to vectorise
+pub const LANES: usize = 64;
+
+/// One channel of a value across a chunk of pixels
+type Lanes = [f32; LANES];
+
+/// A value across a chunk of pixels, stored channel by channel
+type Register = [Lanes; 3];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Sin,
+    Cos,
+    Tan,
+    Atan,
+    Sinh,
+    Cosh,
+    Abs,
+    Reciprocal,
+    Square,
+    SquareRoot,
+    Loge,
+}
+
+impl From<&SingleArgFunction> for UnaryOp {
+    fn from(function: &SingleArgFunction) -> Self {
+        match function {
+            SingleArgFunction::Sin(_) => UnaryOp::Sin,
+            SingleArgFunction::Cos(_) => UnaryOp::Cos,
+            SingleArgFunction::Tan(_) => UnaryOp::Tan,
+            SingleArgFunction::Atan(_) => UnaryOp::Atan,
+            SingleArgFunction::Sinh(_) => UnaryOp::Sinh,
+            SingleArgFunction::Cosh(_) => UnaryOp::Cosh,
+            SingleArgFunction::Abs(_) => UnaryOp::Abs,
+            SingleArgFunction::Reciprocal(_) => UnaryOp::Reciprocal,
+            SingleArgFunction::Square(_) => UnaryOp::Square,
+            SingleArgFunction::SquareRoot(_) => UnaryOp::SquareRoot,
+            SingleArgFunction::Loge(_) => UnaryOp::Loge,
+        }
+    }
+}
+
+/// Applies `f` to every lane, matched once per chunk so each loop is compiled separately
+#[inline(always)]
+fn map(a: &mut Lanes, f: impl Fn(f32) -> f32) {
+    for v in a.iter_mut() {
+        *v = f(*v);
+    }
+}
+
+#[inline(always)]
+fn zip(a: &mut Lanes, b: &Lanes, f: impl Fn(f32, f32) -> f32) {
+    for (v, w) in a.iter_mut().zip(b) {
+        *v = f(*v, *w);
+    }
+}
+
+impl UnaryOp {
+    fn apply(self, a: &mut Lanes) {
+        match self {
+            UnaryOp::Sin => map(a, f32::sin),
+            UnaryOp::Cos => map(a, f32::cos),
+            UnaryOp::Tan => map(a, f32::tan),
+            UnaryOp::Atan => map(a, f32::atan),
+            UnaryOp::Sinh => map(a, f32::sinh),
+            UnaryOp::Cosh => map(a, f32::cosh),
+            UnaryOp::Abs => map(a, f32::abs),
+            UnaryOp::Reciprocal => map(a, |v| 1.0 / v),
+            UnaryOp::Square => map(a, |v| v * v),
+            UnaryOp::SquareRoot => map(a, f32::sqrt),
+            UnaryOp::Loge => map(a, f32::ln),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Min,
+    Max,
+    Avg,
+    Mod,
+    Exponent,
+    And,
+    Or,
+    Xor,
+}
+
+impl From<&TwoArgFunction> for BinaryOp {
+    fn from(function: &TwoArgFunction) -> Self {
+        match function {
+            TwoArgFunction::Add(..) => BinaryOp::Add,
+            TwoArgFunction::Subtract(..) => BinaryOp::Subtract,
+            TwoArgFunction::Multiply(..) => BinaryOp::Multiply,
+            TwoArgFunction::Divide(..) => BinaryOp::Divide,
+            TwoArgFunction::Min(..) => BinaryOp::Min,
+            TwoArgFunction::Max(..) => BinaryOp::Max,
+            TwoArgFunction::Avg(..) => BinaryOp::Avg,
+            TwoArgFunction::Mod(..) => BinaryOp::Mod,
+            TwoArgFunction::Exponent(..) => BinaryOp::Exponent,
+            TwoArgFunction::And(..) => BinaryOp::And,
+            TwoArgFunction::Or(..) => BinaryOp::Or,
+            TwoArgFunction::Xor(..) => BinaryOp::Xor,
+        }
+    }
+}
+
+impl BinaryOp {
+    /// Stores `a op b` in `a`, following the scalar `CpuFunction` reference exactly
+    fn apply(self, a: &mut Lanes, b: &Lanes) {
+        match self {
+            BinaryOp::Add => zip(a, b, |a, b| a + b),
+            BinaryOp::Subtract => zip(a, b, |a, b| a - b),
+            BinaryOp::Multiply => zip(a, b, |a, b| a * b),
+            BinaryOp::Divide => zip(a, b, |a, b| a / b),
+            BinaryOp::Min => zip(a, b, f32::min),
+            BinaryOp::Max => zip(a, b, f32::max),
+            BinaryOp::Avg => zip(a, b, |a, b| (a + b) / 2.0),
+            BinaryOp::Mod => zip(a, b, |a, b| a % b),
+            BinaryOp::Exponent => zip(a, b, wgsl_pow),
+            BinaryOp::And => zip(a, b, |a, b| bitwise(a, b, |a, b| a & b)),
+            BinaryOp::Or => zip(a, b, |a, b| bitwise(a, b, |a, b| a | b)),
+            BinaryOp::Xor => zip(a, b, |a, b| bitwise(a, b, |a, b| a ^ b)),
+        }
+    }
+}
+
+/// Step of a `Program`, where registers are used as a stack and `register` is the depth it acts at
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Instruction {
+    /// Copies coordinate `dim` into every channel of `register`
+    Coord {
+        dim: usize,
+        register: usize,
+    },
+    Constant {
+        value: Rgb,
+        register: usize,
+    },
+    /// Applies `op` to `register` in place
+    Unary {
+        op: UnaryOp,
+        register: usize,
+    },
+    /// Applies `op` to `register` and the register above it, storing the result in `register`
+    Binary {
+        op: BinaryOp,
+        register: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A function flattened into instructions evaluated `LANES` pixels at a time
+///
+/// Produces exactly the values of the scalar `CpuFunction::evaluate`, so it can stand in for it
+pub struct Program {
+    instructions: Vec<Instruction>,
+    registers: usize,
+}
+
+impl Program {
+    pub fn compile(function: &ComputeFunction) -> Result<Self, RenderError> {
+        if function.has_placeholder() {
+            return Err(RenderError::Placeholder);
+        }
+        let mut program = Self {
+            instructions: vec![],
+            registers: 0,
+        };
+        program.push(function, 0);
+        Ok(program)
+    }
+
+    /// Appends the instructions computing `function` into `register`
+    fn push(&mut self, function: &ComputeFunction, register: usize) {
+        self.registers = self.registers.max(register + 1);
+        let instruction = match function {
+            ComputeFunction::Zero(inner) => match **inner {
+                ConstantFunction::Constant(r, g, b) => Instruction::Constant {
+                    value: [r, g, b],
+                    register,
+                },
+                // Dimensions past z read z, as in compiled shaders
+                ConstantFunction::Coord(dim) => Instruction::Coord {
+                    dim: (dim as usize).min(2),
+                    register,
+                },
+            },
+            ComputeFunction::One(inner) => {
+                self.push(inner.get_arg(0usize), register);
+                Instruction::Unary {
+                    op: (&**inner).into(),
+                    register,
+                }
+            }
+            ComputeFunction::Two(inner) => {
+                self.push(inner.get_arg(0usize), register);
+                self.push(inner.get_arg(1usize), register + 1);
+                Instruction::Binary {
+                    op: (&**inner).into(),
+                    register,
+                }
+            }
+            // Can't happen because `compile` rejects placeholders
+            ComputeFunction::Placeholder => unreachable!(),
+        };
+        self.instructions.push(instruction);
+    }
+
+    /// Number of instructions run per chunk
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Evaluates a chunk of pixels whose x, y and z are given per lane, leaving the result in `registers[0]`
+    fn evaluate(&self, coordinates: &[Lanes; 3], registers: &mut [Register]) {
+        for instruction in &self.instructions {
+            match *instruction {
+                Instruction::Coord { dim, register } => {
+                    registers[register] = [coordinates[dim]; 3];
+                }
+                Instruction::Constant { value, register } => {
+                    registers[register] = value.map(|v| [v; LANES]);
+                }
+                Instruction::Unary { op, register } => {
+                    for channel in &mut registers[register] {
+                        op.apply(channel);
+                    }
+                }
+                Instruction::Binary { op, register } => {
+                    let (a, b) = registers[register..].split_at_mut(1);
+                    for (a, b) in a[0].iter_mut().zip(&b[0]) {
+                        op.apply(a, b);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Renders into the interleaved rgb layout of `cpu::processing::generate_buffer`, spreading chunks of pixels
+    /// over the current rayon thread pool
+    pub fn render(&self, image_config: &ImageConfig) -> Vec<f32> {
+        let mut result = vec![0.0; image_config.pixels() as usize * 3];
+        result.par_chunks_mut(LANES * 3).enumerate().for_each_init(
+            || vec![[[0.0; LANES]; 3]; self.registers],
+            |registers, (chunk, output)| {
+                let first_pixel = chunk * LANES;
+                let mut coordinates = [[0.0; LANES]; 3];
+                let [xs, ys, zs] = &mut coordinates;
+                let lanes = xs.iter_mut().zip(ys.iter_mut()).zip(zs.iter_mut());
+                // Lanes past the end of the image are left at zero and never written out
+                for (lane, ((x, y), z)) in lanes.take(output.len() / 3).enumerate() {
+                    (*x, *y, *z) = image_config.coordinates((first_pixel + lane) as u32);
+                }
+                self.evaluate(&coordinates, registers);
+                for (lane, pixel) in output.chunks_exact_mut(3).enumerate() {
+                    for (channel, value) in pixel.iter_mut().enumerate() {
+                        *value = registers[0][channel][lane];
+                    }
+                }
+            },
+        );
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use crate::{
+        compute_functions::{
+            image::{Bounds, Resolution},
+            test_utils::{every_operator, leaf},
+            utils::GenerationMethod,
+        },
+        cpu::processing::generate_buffer,
+        gene::GeneRng,
+    };
+
+    use super::*;
+
+    /// Compares bit patterns, so NaN matches NaN
+    fn assert_matches_reference(function: &ComputeFunction, image_config: &ImageConfig) {
+        let program = Program::compile(function).unwrap();
+        let result = program.render(image_config);
+        let expected = generate_buffer(image_config, function);
+        assert_eq!(result.len(), expected.len());
+        for (i, (value, expected)) in result.iter().zip(&expected).enumerate() {
+            assert_eq!(
+                value.to_bits(),
+                expected.to_bits(),
+                "{} at {}: {} != {}",
+                function,
+                i,
+                value,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_every_operator_matches_reference() {
+        let config = ImageConfig {
+            resolution: Resolution::new(13, 11),
+            bounds: Bounds::new(-2.0, -2.0, 0.5, 4.0, 4.0),
+        };
+        for function in every_operator([1.5, -0.5, 2.0]) {
+            assert_matches_reference(&function, &config);
+        }
+        assert_matches_reference(&leaf(ConstantFunction::Coord(7)), &config);
+    }
+
+    #[test]
+    fn test_random_trees_match_reference() {
+        let mut rng = GeneRng::seed_from_u64(0);
+        for (width, height) in [(64, 1), (37, 23), (1, 1), (0, 5), (200, 3)] {
+            let config = ImageConfig {
+                resolution: Resolution::new(width, height),
+                bounds: Bounds::new(-1.0, -1.0, 0.25, 2.0, 2.0),
+            };
+            for _ in 0..10 {
+                let function =
+                    ComputeFunction::random_deep(GenerationMethod::Grow, 1, 7, &mut rng).unwrap();
+                assert_matches_reference(&function, &config);
+            }
+        }
+    }
+
+    #[test]
+    fn test_registers() {
+        let program = Program::compile(&"x + (y * (z - sin(x)))".parse().unwrap()).unwrap();
+        assert_eq!(program.len(), 8);
+        assert_eq!(program.registers, 4);
+        assert!(matches!(
+            Program::compile(&"x + _".parse().unwrap()),
+            Err(RenderError::Placeholder)
+        ));
+    }
+}
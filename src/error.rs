@@ -1,5 +1,7 @@
 use thiserror::Error;
-use wgpu::RequestDeviceError;
+use wgpu::{BufferAsyncError, RequestDeviceError};
+
+use crate::gene::Gene;
 
 #[derive(Debug, Error)]
 pub enum GpuError {
@@ -7,10 +9,113 @@ pub enum GpuError {
     NoAdapter,
     #[error("Failed to request device: {0}")]
     RequestDeviceError(RequestDeviceError),
+    #[error("Gpu adapter lacks required features {0:?}")]
+    UnsupportedFeatures(wgpu::Features),
+}
+
+#[derive(Debug, Error)]
+/// Failure to render a gene, after which the gpu instance can still be used unless the device was lost
+pub enum RenderError {
+    #[error(transparent)]
+    Shader(#[from] ShaderError),
+    #[error(transparent)]
+    Bytecode(#[from] BytecodeError),
+    #[error("Gpu device was lost: {0}")]
+    DeviceLost(String),
+    #[error("Failed to map output buffer: {0}")]
+    BufferMap(BufferAsyncError),
+    #[error("Gpu ran out of memory: {0}")]
+    OutOfMemory(String),
+    #[error("Gpu validation failed: {0}")]
+    Validation(String),
+    #[error("Expected {expected} constant(s) but got {found}")]
+    ConstantCount { expected: usize, found: usize },
+    #[error("Function still contains placeholders")]
+    Placeholder,
+    #[error("Gene differs in structure from the prepared gene")]
+    StructureMismatch,
+    #[error("Failed to serialize render arguments: {0}")]
+    Serialize(#[from] bincode::Error),
+}
+
+impl From<wgpu::Error> for RenderError {
+    fn from(error: wgpu::Error) -> Self {
+        match error {
+            wgpu::Error::OutOfMemory { .. } => RenderError::OutOfMemory(error.to_string()),
+            wgpu::Error::Validation { description, .. } => RenderError::Validation(description),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+/// Failure while streaming a rendered image to disk
+pub enum EncodeError {
+    #[error(transparent)]
+    Render(#[from] RenderError),
+    #[error("Failed to encode png: {0}")]
+    Png(#[from] png::EncodingError),
+    #[error("Failed to encode tiff: {0}")]
+    Tiff(#[from] tiff::TiffError),
+    #[error("Failed to write image: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Unsupported image format for {0}")]
+    UnsupportedFormat(String),
 }
 
 #[derive(Debug, Error)]
 pub enum ApplicationError {
     #[error("Bad argument")]
     BadArg,
+    #[error(transparent)]
+    Render(#[from] RenderError),
+}
+
+#[derive(Debug, Error)]
+#[error("Generated shader is invalid for {}: {diagnostic}", gene.function())]
+/// A gene whose generated shader failed validation
+pub struct ShaderError {
+    pub gene: Gene,
+    /// The generated wgsl
+    pub shader_code: String,
+    /// Validation message, annotated with the offending span of `shader_code`
+    pub diagnostic: String,
+}
+
+#[derive(Debug, Error, Clone, PartialEq)]
+/// A function that cannot be run by the gpu interpreter
+pub enum BytecodeError {
+    #[error("Placeholders cannot be interpreted")]
+    Placeholder,
+    #[error("Function needs a stack of {0} values, more than the interpreter's {1}")]
+    StackOverflow(usize, usize),
+}
+
+#[derive(Debug, Error, PartialEq)]
+/// Failure to parse an expression, each variant holding the byte position it occurred at
+pub enum ParseError {
+    #[error("Unexpected character '{1}' at position {0}")]
+    UnexpectedChar(usize, char),
+    #[error("Unexpected end of input at position {0}")]
+    UnexpectedEnd(usize),
+    #[error("Unknown function '{1}' at position {0}")]
+    UnknownFunction(usize, String),
+    #[error("Invalid number '{1}' at position {0}")]
+    InvalidNumber(usize, String),
+    #[error("Expected {1} argument(s) at position {0}")]
+    WrongArgCount(usize, usize),
+    #[error("Expression nested more than {1} levels deep at position {0}")]
+    TooDeep(usize, usize),
+}
+
+impl ParseError {
+    pub fn position(&self) -> usize {
+        match self {
+            ParseError::UnexpectedChar(position, _)
+            | ParseError::UnexpectedEnd(position)
+            | ParseError::UnknownFunction(position, _)
+            | ParseError::InvalidNumber(position, _)
+            | ParseError::WrongArgCount(position, _)
+            | ParseError::TooDeep(position, _) => *position,
+        }
+    }
 }
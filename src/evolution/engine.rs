@@ -0,0 +1,286 @@
+use rand::{Rng, SeedableRng};
+
+use crate::{
+    backend::RenderBackend,
+    compute_functions::{image::ImageConfig, utils::GenerationMethod},
+    error::ApplicationError,
+    gene::{mating::CrossoverConfig, mutation::MutationConfig, Gene, GeneRng},
+};
+
+use super::{fitness::Fitness, selection::Selection};
+
+#[derive(Debug, Clone)]
+pub struct Individual {
+    pub gene: Gene,
+    pub fitness: f32,
+}
+
+#[derive(Debug, Clone)]
+/// Individuals of one generation, ordered from fittest to least fit
+pub struct Population {
+    pub generation: u32,
+    pub individuals: Vec<Individual>,
+}
+
+impl Population {
+    pub fn best(&self) -> Option<&Individual> {
+        self.individuals.first()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EvolutionConfig {
+    /// Seed for the whole run, so the same config always evolves the same genes
+    pub seed: u64,
+    pub population_size: usize,
+    /// Number of fittest individuals copied unchanged into the next generation
+    pub elitism: usize,
+    /// Chance of a child being bred by crossover rather than copied from a single parent
+    pub crossover_rate: f32,
+    /// Depth range used to seed the first generation with ramped half-and-half
+    pub min_depth: u32,
+    pub max_depth: u32,
+    pub mutation: MutationConfig,
+    pub crossover: CrossoverConfig,
+    /// Image every individual is rendered at for scoring
+    pub image_config: ImageConfig,
+}
+
+pub struct EvolutionEngine<F: Fitness> {
+    pub config: EvolutionConfig,
+    pub selection: Box<dyn Selection>,
+    pub fitness: F,
+}
+
+impl<F: Fitness> EvolutionEngine<F> {
+    pub fn new(config: EvolutionConfig, selection: Box<dyn Selection>, fitness: F) -> Self {
+        Self {
+            config,
+            selection,
+            fitness,
+        }
+    }
+
+    /// Random genes for the first generation, using ramped half-and-half
+    /// Each gene gets its own seed drawn from `rng`, so it can be regenerated alone with `Gene::random`
+    pub fn initial_genes<R: Rng>(&self, rng: &mut R) -> Result<Vec<Gene>, ApplicationError> {
+        (0..self.config.population_size)
+            .map(|i| {
+                let (method, depth) =
+                    GenerationMethod::ramped(i, self.config.min_depth, self.config.max_depth);
+                Gene::random(rng.gen(), method, self.config.min_depth, depth)
+            })
+            .collect()
+    }
+
+    /// Renders and scores every gene
+    ///
+    /// A simplified copy of each gene is rendered, so shaders stay small, while the population keeps the genes
+    /// unchanged.
+    /// Genes that fail to render get a fitness of negative infinity, while a failure of the backend itself is
+    /// returned as an error
+    pub fn evaluate(
+        &self,
+        generation: u32,
+        genes: Vec<Gene>,
+        backend: &dyn RenderBackend,
+    ) -> Result<Population, ApplicationError> {
+        let image_config = &self.config.image_config;
+        let simplified: Vec<Gene> = genes
+            .iter()
+            .map(|gene| gene.with_function(gene.function().simplify()))
+            .collect();
+        let buffers = backend.render_batch(&simplified, image_config)?;
+        let mut individuals: Vec<Individual> = genes
+            .into_iter()
+            .zip(buffers)
+            .map(|(gene, buffer)| {
+                let fitness = match buffer {
+                    Ok(buffer) => self.fitness.fitness(&gene, &buffer, image_config),
+                    Err(_) => f32::NEG_INFINITY,
+                };
+                let fitness = if fitness.is_nan() {
+                    f32::NEG_INFINITY
+                } else {
+                    fitness
+                };
+                Individual { gene, fitness }
+            })
+            .collect();
+        individuals.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+        Ok(Population {
+            generation,
+            individuals,
+        })
+    }
+
+    /// Breeds the genes of the next generation from a scored population
+    pub fn breed<R: Rng>(
+        &self,
+        population: &Population,
+        rng: &mut R,
+    ) -> Result<Vec<Gene>, ApplicationError> {
+        let individuals = &population.individuals;
+        if individuals.is_empty() {
+            return Err(ApplicationError::BadArg);
+        }
+        let mut genes: Vec<Gene> = individuals
+            .iter()
+            .take(self.config.elitism)
+            .map(|i| i.gene.clone())
+            .collect();
+        while genes.len() < self.config.population_size {
+            let first = &individuals[self.selection.select(individuals, rng)].gene;
+            let children = if rng.gen::<f32>() < self.config.crossover_rate {
+                let second = &individuals[self.selection.select(individuals, rng)].gene;
+                let (a, b) = first.crossover(second, &self.config.crossover, rng)?;
+                vec![a, b]
+            } else {
+                vec![first.clone()]
+            };
+            for child in children {
+                if genes.len() < self.config.population_size {
+                    genes.push(child.mutate(&self.config.mutation, rng)?);
+                }
+            }
+        }
+        Ok(genes)
+    }
+
+    /// Breeds and scores the generation after `population`
+    pub fn step<R: Rng>(
+        &self,
+        population: &Population,
+        backend: &dyn RenderBackend,
+        rng: &mut R,
+    ) -> Result<Population, ApplicationError> {
+        let genes = self.breed(population, rng)?;
+        self.evaluate(population.generation + 1, genes, backend)
+    }
+
+    /// Seeds a population from `config.seed` and evolves it for `generations` generations
+    pub fn run(
+        &self,
+        generations: u32,
+        backend: &dyn RenderBackend,
+    ) -> Result<Population, ApplicationError> {
+        let mut rng = GeneRng::seed_from_u64(self.config.seed);
+        let genes = self.initial_genes(&mut rng)?;
+        let mut population = self.evaluate(0, genes, backend)?;
+        for _ in 0..generations {
+            population = self.step(&population, backend, &mut rng)?;
+        }
+        Ok(population)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use rand::SeedableRng;
+
+    use crate::{
+        backend::{CpuBackend, NullBackend},
+        compute_functions::image::{Bounds, Resolution},
+        error::RenderError,
+        evolution::{
+            fitness::ColourVariance,
+            selection::{Rank, Tournament},
+        },
+    };
+
+    use super::*;
+
+    fn test_config() -> EvolutionConfig {
+        EvolutionConfig {
+            seed: 0,
+            population_size: 16,
+            elitism: 2,
+            crossover_rate: 0.7,
+            min_depth: 2,
+            max_depth: 4,
+            mutation: MutationConfig::default(),
+            crossover: CrossoverConfig::default(),
+            image_config: ImageConfig {
+                resolution: Resolution::new(8, 8),
+                bounds: Bounds::new(-1.0, -1.0, 0.0, 2.0, 2.0),
+            },
+        }
+    }
+
+    #[test]
+    fn test_elitism_keeps_best() {
+        let engine = EvolutionEngine::new(
+            test_config(),
+            Box::new(Tournament { size: 3 }),
+            ColourVariance,
+        );
+        let mut rng = GeneRng::seed_from_u64(0);
+        let backend = CpuBackend::default();
+        let genes = engine.initial_genes(&mut rng).unwrap();
+        let mut population = engine.evaluate(0, genes, &backend).unwrap();
+        for _ in 0..3 {
+            let next = engine.step(&population, &backend, &mut rng).unwrap();
+            assert_eq!(next.individuals.len(), 16);
+            assert!(next.best().unwrap().fitness >= population.best().unwrap().fitness);
+            population = next;
+        }
+        assert_eq!(population.generation, 3);
+    }
+
+    #[test]
+    fn test_run_is_reproducible() {
+        let engine = EvolutionEngine::new(test_config(), Box::new(Rank), ColourVariance);
+        let backend = CpuBackend::default();
+        let genes = |population: Population| {
+            let genes: Vec<Gene> = population.individuals.into_iter().map(|i| i.gene).collect();
+            bincode::serialize(&genes).unwrap()
+        };
+        let first = genes(engine.run(3, &backend).unwrap());
+        let second = genes(engine.run(3, &backend).unwrap());
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_flat_images_keep_population_size() {
+        let engine = EvolutionEngine::new(test_config(), Box::new(Rank), ColourVariance);
+        let population = engine.run(2, &NullBackend::default()).unwrap();
+        assert_eq!(population.generation, 2);
+        assert_eq!(population.individuals.len(), 16);
+        assert!(population.individuals.iter().all(|i| i.fitness == 0.0));
+    }
+
+    /// Records the functions it is asked to render
+    #[derive(Default)]
+    struct Rendered {
+        functions: RefCell<Vec<String>>,
+    }
+
+    impl RenderBackend for Rendered {
+        fn name(&self) -> &'static str {
+            "rendered"
+        }
+
+        fn render(&self, gene: &Gene, image_config: &ImageConfig) -> Result<Vec<f32>, RenderError> {
+            self.functions
+                .borrow_mut()
+                .push(gene.function().to_string());
+            NullBackend::default().render(gene, image_config)
+        }
+    }
+
+    #[test]
+    fn test_simplified_copies_are_rendered() {
+        let engine = EvolutionEngine::new(test_config(), Box::new(Rank), ColourVariance);
+        let function = "x * rgb(1, 1, 1) + log(rgb(0, 0, 0) - x) * rgb(0, 0, 0)";
+        let genes = vec![Gene::new(0, function.parse().unwrap())];
+        let backend = Rendered::default();
+        let population = engine.evaluate(0, genes, &backend).unwrap();
+        assert_eq!(*backend.functions.borrow(), ["x"]);
+        assert_eq!(
+            population.individuals[0].gene.function(),
+            &function.parse().unwrap()
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use crate::{compute_functions::image::ImageConfig, gene::Gene};
+
+pub trait Fitness {
+    /// Scores a gene from its rendered buffer, laid out as in `RenderBackend::render`
+    /// Higher scores are fitter
+    fn fitness(&self, gene: &Gene, buffer: &[f32], image_config: &ImageConfig) -> f32;
+}
+
+impl<F: Fn(&Gene, &[f32], &ImageConfig) -> f32> Fitness for F {
+    fn fitness(&self, gene: &Gene, buffer: &[f32], image_config: &ImageConfig) -> f32 {
+        self(gene, buffer, image_config)
+    }
+}
+
+/// Rewards images with a wide spread of colour, scoring the mean variance of each channel
+/// Images containing non-finite values score negative infinity
+pub struct ColourVariance;
+
+impl Fitness for ColourVariance {
+    fn fitness(&self, _gene: &Gene, buffer: &[f32], _image_config: &ImageConfig) -> f32 {
+        if buffer.is_empty() || buffer.iter().any(|v| !v.is_finite()) {
+            return f32::NEG_INFINITY;
+        }
+        let pixels = (buffer.len() / 3) as f32;
+        let variance: f32 = (0..3)
+            .map(|channel| {
+                let values = || buffer.iter().skip(channel).step_by(3);
+                let mean = values().sum::<f32>() / pixels;
+                values().map(|v| (v - mean).powi(2)).sum::<f32>() / pixels
+            })
+            .sum();
+        variance / 3.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compute_functions::{
+        image::{Bounds, Resolution},
+        ComputeFunction,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_colour_variance() {
+        let gene = Gene::new(0, ComputeFunction::Placeholder);
+        let config = ImageConfig {
+            resolution: Resolution::new(2, 1),
+            bounds: Bounds::new(0.0, 0.0, 0.0, 1.0, 1.0),
+        };
+        let flat = ColourVariance.fitness(&gene, &[0.5; 6], &config);
+        assert_eq!(flat, 0.0);
+        let varied = ColourVariance.fitness(&gene, &[0.0, 0.0, 0.0, 1.0, 1.0, 1.0], &config);
+        assert_eq!(varied, 0.25);
+        let broken = ColourVariance.fitness(&gene, &[f32::NAN; 6], &config);
+        assert_eq!(broken, f32::NEG_INFINITY);
+    }
+}
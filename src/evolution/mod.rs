@@ -0,0 +1,3 @@
+pub mod engine;
+pub mod fitness;
+pub mod selection;
@@ -0,0 +1,138 @@
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng, RngCore};
+
+use super::engine::Individual;
+
+pub trait Selection {
+    /// Picks a parent from a non-empty population, returning its index
+    fn select(&self, population: &[Individual], rng: &mut dyn RngCore) -> usize;
+}
+
+/// Indices of `population` ordered from fittest to least fit
+fn ranked(population: &[Individual]) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..population.len()).collect();
+    indices.sort_by(|a, b| population[*b].fitness.total_cmp(&population[*a].fitness));
+    indices
+}
+
+/// Best of `size` individuals drawn uniformly at random
+pub struct Tournament {
+    pub size: usize,
+}
+
+impl Selection for Tournament {
+    fn select(&self, population: &[Individual], rng: &mut dyn RngCore) -> usize {
+        (0..self.size.max(1))
+            .map(|_| rng.gen_range(0..population.len()))
+            .max_by(|a, b| population[*a].fitness.total_cmp(&population[*b].fitness))
+            .unwrap()
+    }
+}
+
+/// Fitness proportionate selection, with fitness shifted so the least fit individual has weight zero
+pub struct Roulette;
+
+impl Selection for Roulette {
+    fn select(&self, population: &[Individual], rng: &mut dyn RngCore) -> usize {
+        let finite = || {
+            population
+                .iter()
+                .map(|i| i.fitness)
+                .filter(|f| f.is_finite())
+        };
+        let min = finite().fold(f32::INFINITY, f32::min);
+        let weights = population.iter().map(|i| {
+            if i.fitness.is_finite() {
+                i.fitness - min
+            } else {
+                0.0
+            }
+        });
+        match WeightedIndex::new(weights) {
+            Ok(dist) => dist.sample(rng),
+            // Every weight is zero, so all individuals are equally fit
+            Err(_) => rng.gen_range(0..population.len()),
+        }
+    }
+}
+
+/// Linear ranking, where the fittest of n individuals is n times as likely to be picked as the least fit
+pub struct Rank;
+
+impl Selection for Rank {
+    fn select(&self, population: &[Individual], rng: &mut dyn RngCore) -> usize {
+        let indices = ranked(population);
+        let n = indices.len();
+        // Can unwrap because the population is non-empty so every weight is positive
+        let dist = WeightedIndex::new((0..n).map(|rank| n - rank)).unwrap();
+        indices[dist.sample(rng)]
+    }
+}
+
+/// Uniform choice among the fittest `fraction` of the population
+pub struct Truncation {
+    pub fraction: f32,
+}
+
+impl Selection for Truncation {
+    fn select(&self, population: &[Individual], rng: &mut dyn RngCore) -> usize {
+        let indices = ranked(population);
+        let count =
+            ((indices.len() as f32 * self.fraction).ceil() as usize).clamp(1, indices.len());
+        indices[rng.gen_range(0..count)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use crate::{
+        compute_functions::ComputeFunction,
+        gene::{Gene, GeneRng},
+    };
+
+    use super::*;
+
+    fn population() -> Vec<Individual> {
+        [1.0, 4.0, f32::NEG_INFINITY, 2.0, 3.0]
+            .into_iter()
+            .map(|fitness| Individual {
+                gene: Gene::new(0, ComputeFunction::Placeholder),
+                fitness,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_selection() {
+        let population = population();
+        let mut rng = GeneRng::seed_from_u64(0);
+        // Picks out of 1000 per individual, fixed by the seed
+        let selections: [(&dyn Selection, &str, [u32; 5]); 4] = [
+            (
+                &Tournament { size: 3 },
+                "tournament",
+                [71, 475, 9, 152, 293],
+            ),
+            (&Roulette, "roulette", [0, 494, 0, 166, 340]),
+            (&Rank, "rank", [134, 336, 66, 195, 269]),
+            (
+                &Truncation { fraction: 0.4 },
+                "truncation",
+                [0, 495, 0, 0, 505],
+            ),
+        ];
+        for (selection, name, expected) in selections {
+            let mut counts = [0; 5];
+            for _ in 0..1000 {
+                counts[selection.select(&population, &mut rng)] += 1;
+            }
+            assert_eq!(counts, expected, "{}", name);
+            // The fittest individual is always favoured over the least fit
+            assert!(counts[1] > counts[2], "{}", name);
+        }
+        // Truncation never picks outside the top 40%
+        let truncation = Truncation { fraction: 0.4 };
+        assert!((0..100).all(|_| [1, 4].contains(&truncation.select(&population, &mut rng))));
+    }
+}
@@ -0,0 +1,237 @@
+use std::mem::swap;
+
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    compute_functions::{
+        path::{NodePath, TraversalOrder},
+        ComputeFunction,
+    },
+    error::ApplicationError,
+};
+
+use super::Gene;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrossoverMethod {
+    /// Swaps a randomly chosen subtree of each parent
+    Subtree,
+    /// Swaps the subtrees at a single point in the region where both parents have the same shape
+    OnePoint,
+    /// Walks the shared region, swapping operators and boundary subtrees independently
+    Uniform,
+}
+
+#[derive(Debug, Clone)]
+pub struct CrossoverConfig {
+    pub method: CrossoverMethod,
+    /// Offspring deeper than this are rejected
+    pub max_depth: u32,
+    /// Number of tries at producing offspring within `max_depth` before falling back to copies of the parents
+    /// that are within it
+    pub attempts: u32,
+    /// Chance of swapping at each node during uniform crossover
+    pub uniform_rate: f32,
+}
+
+impl Default for CrossoverConfig {
+    fn default() -> Self {
+        Self {
+            method: CrossoverMethod::Subtree,
+            max_depth: 8,
+            attempts: 5,
+            uniform_rate: 0.5,
+        }
+    }
+}
+
+/// Paths that exist in both trees and whose ancestors have matching arities
+fn common_region(a: &ComputeFunction, b: &ComputeFunction) -> Vec<NodePath> {
+    let mut paths = vec![];
+    let mut frontier = vec![(a, b, NodePath::root())];
+    while let Some((a, b, path)) = frontier.pop() {
+        let (a_children, b_children) = (a.children(), b.children());
+        if a_children.len() == b_children.len() {
+            for (i, (a, b)) in a_children.into_iter().zip(b_children).enumerate() {
+                frontier.push((a, b, path.child(i)));
+            }
+        }
+        paths.push(path);
+    }
+    paths
+}
+
+fn swap_subtrees(
+    a: &mut ComputeFunction,
+    a_path: &NodePath,
+    b: &mut ComputeFunction,
+    b_path: &NodePath,
+) {
+    // Can unwrap because paths are only ever taken from their own trees
+    swap(a.get_mut(a_path).unwrap(), b.get_mut(b_path).unwrap());
+}
+
+fn uniform<R: Rng + ?Sized>(
+    a: &mut ComputeFunction,
+    b: &mut ComputeFunction,
+    rate: f32,
+    rng: &mut R,
+) {
+    let arity = a.children().len();
+    if arity > 0 && arity == b.children().len() {
+        if rng.gen::<f32>() < rate {
+            // Swap just the operators by swapping the nodes then swapping their arguments back
+            swap(a, b);
+            for (a, b) in a.children_mut().into_iter().zip(b.children_mut()) {
+                swap(a, b);
+            }
+        }
+        for (a, b) in a.children_mut().into_iter().zip(b.children_mut()) {
+            uniform(a, b, rate, rng);
+        }
+    } else if rng.gen::<f32>() < rate {
+        swap(a, b);
+    }
+}
+
+impl Gene {
+    /// Breeds two children from self and `other`, each recording both parents
+    ///
+    /// Children are never deeper than `config.max_depth`, so this fails if neither parent is within it and no
+    /// attempt produced valid offspring
+    pub fn crossover<R: Rng + ?Sized>(
+        &self,
+        other: &Gene,
+        config: &CrossoverConfig,
+        rng: &mut R,
+    ) -> Result<(Gene, Gene), ApplicationError> {
+        if config.max_depth == 0 {
+            return Err(ApplicationError::BadArg);
+        }
+        let fits = |function: &ComputeFunction| function.depth() <= config.max_depth;
+        let mut offspring = None;
+        for _ in 0..config.attempts {
+            let (mut a, mut b) = (self.function.clone(), other.function.clone());
+            match config.method {
+                CrossoverMethod::Subtree => {
+                    let a_paths = a.paths(TraversalOrder::BreadthFirst);
+                    let b_paths = b.paths(TraversalOrder::BreadthFirst);
+                    // Can unwrap because every tree has at least a root
+                    let a_path = a_paths.choose(rng).unwrap();
+                    let b_path = b_paths.choose(rng).unwrap();
+                    swap_subtrees(&mut a, a_path, &mut b, b_path);
+                }
+                CrossoverMethod::OnePoint => {
+                    let region = common_region(&a, &b);
+                    let path = region.choose(rng).unwrap();
+                    swap_subtrees(&mut a, path, &mut b, path);
+                }
+                CrossoverMethod::Uniform => uniform(&mut a, &mut b, config.uniform_rate, rng),
+            }
+            if fits(&a) && fits(&b) {
+                offspring = Some((a, b));
+                break;
+            }
+        }
+        let (first, second) = match offspring {
+            Some(offspring) => offspring,
+            None => {
+                // A parent that is too deep is replaced by the other one
+                let fallback = [&self.function, &other.function]
+                    .into_iter()
+                    .find(|function| fits(function))
+                    .ok_or(ApplicationError::BadArg)?;
+                let copy = |function: &ComputeFunction| {
+                    if fits(function) {
+                        function.clone()
+                    } else {
+                        fallback.clone()
+                    }
+                };
+                (copy(&self.function), copy(&other.function))
+            }
+        };
+        let parents = Some([self.id(), other.id()]);
+        Ok((
+            Gene {
+                seed: self.seed,
+                function: first,
+                parents,
+            },
+            Gene {
+                seed: other.seed,
+                function: second,
+                parents,
+            },
+        ))
+    }
+
+    /// Breeds a single child, the first of `crossover`
+    pub fn mate<R: Rng + ?Sized>(
+        &self,
+        other: &Gene,
+        config: &CrossoverConfig,
+        rng: &mut R,
+    ) -> Result<Gene, ApplicationError> {
+        Ok(self.crossover(other, config, rng)?.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use crate::{compute_functions::utils::GenerationMethod, gene::GeneRng};
+
+    use super::*;
+
+    fn random_gene(seed: u64) -> Gene {
+        let mut rng = GeneRng::seed_from_u64(seed);
+        let function =
+            ComputeFunction::random_deep(GenerationMethod::Full, 1, 4, &mut rng).unwrap();
+        Gene::new(seed, function)
+    }
+
+    #[test]
+    fn test_crossover() {
+        let (a, b) = (random_gene(0), random_gene(1));
+        let mut rng = GeneRng::seed_from_u64(2);
+        for method in [
+            CrossoverMethod::Subtree,
+            CrossoverMethod::OnePoint,
+            CrossoverMethod::Uniform,
+        ] {
+            let config = CrossoverConfig {
+                method,
+                max_depth: 5,
+                ..Default::default()
+            };
+            for _ in 0..10 {
+                let (first, second) = a.crossover(&b, &config, &mut rng).unwrap();
+                // Material is exchanged, never created or lost
+                assert_eq!(
+                    first.function().bfs().len() + second.function().bfs().len(),
+                    a.function().bfs().len() + b.function().bfs().len()
+                );
+                assert!(first.function().depth() <= 5 && second.function().depth() <= 5);
+                assert_eq!(first.parents(), Some([a.id(), b.id()]));
+                assert_eq!(second.parents(), Some([a.id(), b.id()]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_depth_limit_falls_back_to_parents() {
+        let (shallow, deep) = (Gene::new(0, "x".parse().unwrap()), random_gene(4));
+        let config = CrossoverConfig {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let mut rng = GeneRng::seed_from_u64(5);
+        let (first, second) = shallow.crossover(&deep, &config, &mut rng).unwrap();
+        assert!(first.function().depth() <= config.max_depth);
+        assert!(second.function().depth() <= config.max_depth);
+        // Neither parent fits, so no child can be bred
+        assert!(deep.mate(&random_gene(3), &config, &mut rng).is_err());
+    }
+}
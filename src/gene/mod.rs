@@ -1,14 +1,121 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 use serde::{Deserialize, Serialize};
 
-use crate::compute_functions::ComputeFunction;
+use crate::{
+    compute_functions::{
+        shader::{validate_shader, ShaderFunction},
+        shader_builder::ConstantMode,
+        utils::{fnv1a, GenerationMethod},
+        ComputeFunction,
+    },
+    error::{ApplicationError, ShaderError},
+};
 
 pub mod mating;
 pub mod mutation;
 
+/// Rng behind every seeded operation, chosen because its output is the same across platforms and releases
+/// so a seed always reproduces the same genes
+pub type GeneRng = ChaCha8Rng;
+
+/// Identifies a gene by the contents that determine its image
+pub type GeneId = u64;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 /// Contains all information needed to perfectly reproduce an image
 /// Things like z, resolution and bounds are more part of the "phenotype" and so are not contained here
 pub struct Gene {
-    seed: f32,
+    seed: u64,
     function: ComputeFunction,
+    /// Ids of the genes this one was bred from, if any
+    #[serde(default)]
+    parents: Option<[GeneId; 2]>,
+}
+
+impl Gene {
+    pub fn new(seed: u64, function: ComputeFunction) -> Self {
+        Self {
+            seed,
+            function,
+            parents: None,
+        }
+    }
+
+    /// Generates a gene whose function is determined entirely by `seed`
+    pub fn random(
+        seed: u64,
+        method: GenerationMethod,
+        min_depth: u32,
+        max_depth: u32,
+    ) -> Result<Self, ApplicationError> {
+        let mut rng = GeneRng::seed_from_u64(seed);
+        let function = ComputeFunction::random_deep(method, min_depth, max_depth, &mut rng)?;
+        Ok(Self::new(seed, function))
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn function(&self) -> &ComputeFunction {
+        &self.function
+    }
+
+    pub fn parents(&self) -> Option<[GeneId; 2]> {
+        self.parents
+    }
+
+    /// Copy of this gene with a different function, keeping its seed and parents
+    pub fn with_function(&self, function: ComputeFunction) -> Self {
+        Self {
+            function,
+            ..self.clone()
+        }
+    }
+
+    /// Generates the shader rendering this gene, checking it is valid wgsl before it reaches the gpu
+    pub fn shader_code(&self, constant_mode: ConstantMode) -> Result<String, ShaderError> {
+        let shader_code = self.function.get_shader_code_with(constant_mode);
+        match validate_shader(&shader_code) {
+            Ok(()) => Ok(shader_code),
+            Err(diagnostic) => Err(ShaderError {
+                gene: self.clone(),
+                shader_code,
+                diagnostic,
+            }),
+        }
+    }
+
+    /// FNV-1a hash of the seed and the function's structural hash, so identical genes share an id
+    pub fn id(&self) -> GeneId {
+        // Can unwrap because serialising to a vec cannot fail
+        fnv1a(&bincode::serialize(&(self.seed, self.function.structural_hash())).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_is_reproducible() {
+        let first = Gene::random(42, GenerationMethod::Grow, 2, 6).unwrap();
+        let second = Gene::random(42, GenerationMethod::Grow, 2, 6).unwrap();
+        assert_eq!(
+            bincode::serialize(&first).unwrap(),
+            bincode::serialize(&second).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_shader_code() {
+        let gene = Gene::random(0, GenerationMethod::Full, 3, 3).unwrap();
+        assert!(gene.shader_code(ConstantMode::Inline).is_ok());
+        let incomplete = Gene::new(1, "sin(x) + _".parse().unwrap());
+        let error = incomplete.shader_code(ConstantMode::Buffer).unwrap_err();
+        assert_eq!(error.gene.seed(), 1);
+        assert!(error.shader_code.contains("placeholder"));
+        assert!(error.diagnostic.contains("placeholder"), "{}", error);
+    }
 }
@@ -0,0 +1,260 @@
+use std::mem::{discriminant, take};
+
+use enum_methods::EnumMethods;
+use rand::{seq::IteratorRandom, seq::SliceRandom, Rng};
+use strum::IntoEnumIterator;
+
+use crate::{
+    compute_functions::{
+        path::{NodePath, TraversalOrder},
+        utils::GenerationMethod,
+        ComputeFunction, ConstantFunction, SingleArgFunction, TwoArgFunction,
+    },
+    error::ApplicationError,
+};
+
+use super::Gene;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::EnumIter)]
+pub enum MutationOperator {
+    /// Swaps an operator for another of the same arity, keeping its arguments
+    Point,
+    /// Replaces a subtree with a freshly generated one
+    Subtree,
+    /// Replaces the whole tree with one of its own subtrees
+    Hoist,
+    /// Replaces a subtree with a single leaf
+    Shrink,
+    /// Nudges the channels of a constant
+    Constant,
+    /// Changes which coordinate a coordinate leaf reads
+    Coord,
+}
+
+#[derive(Debug, Clone)]
+/// Probability of each operator being applied per mutation
+pub struct MutationConfig {
+    pub point: f32,
+    pub subtree: f32,
+    pub hoist: f32,
+    pub shrink: f32,
+    pub constant: f32,
+    pub coord: f32,
+    /// Largest change made to each channel by a constant perturbation
+    pub constant_scale: f32,
+    /// Maximum depth of subtrees created by subtree mutation
+    pub subtree_depth: u32,
+}
+
+impl Default for MutationConfig {
+    fn default() -> Self {
+        Self {
+            point: 0.2,
+            subtree: 0.1,
+            hoist: 0.05,
+            shrink: 0.05,
+            constant: 0.2,
+            coord: 0.1,
+            constant_scale: 0.1,
+            subtree_depth: 3,
+        }
+    }
+}
+
+impl MutationConfig {
+    pub fn probability(&self, operator: MutationOperator) -> f32 {
+        match operator {
+            MutationOperator::Point => self.point,
+            MutationOperator::Subtree => self.subtree,
+            MutationOperator::Hoist => self.hoist,
+            MutationOperator::Shrink => self.shrink,
+            MutationOperator::Constant => self.constant,
+            MutationOperator::Coord => self.coord,
+        }
+    }
+}
+
+fn random_path<R: Rng + ?Sized>(
+    function: &ComputeFunction,
+    rng: &mut R,
+    predicate: impl Fn(&NodePath, &ComputeFunction) -> bool,
+) -> Option<NodePath> {
+    function
+        .paths(TraversalOrder::BreadthFirst)
+        .into_iter()
+        .filter(|path| predicate(path, function.get(path).unwrap()))
+        .choose(rng)
+}
+
+impl MutationOperator {
+    /// Applies the operator to a random node of `function`
+    /// Returns whether the tree was changed, which is not the case when no node fits the operator
+    pub fn apply<R: Rng + ?Sized>(
+        &self,
+        function: &mut ComputeFunction,
+        config: &MutationConfig,
+        rng: &mut R,
+    ) -> Result<bool, ApplicationError> {
+        let Some(path) = random_path(function, rng, |path, node| match self {
+            MutationOperator::Point | MutationOperator::Subtree => true,
+            MutationOperator::Hoist => !path.is_root(),
+            MutationOperator::Shrink => !node.children().is_empty(),
+            MutationOperator::Constant => matches!(
+                node,
+                ComputeFunction::Zero(c) if matches!(**c, ConstantFunction::Constant(..))
+            ),
+            MutationOperator::Coord => matches!(
+                node,
+                ComputeFunction::Zero(c) if matches!(**c, ConstantFunction::Coord(_))
+            ),
+        }) else {
+            return Ok(false);
+        };
+        // Can unwrap because the path was taken from the tree
+        let node = function.get_mut(&path).unwrap();
+        match self {
+            MutationOperator::Point => point_mutate(node, rng)?,
+            MutationOperator::Subtree => {
+                *node = ComputeFunction::random_deep(
+                    GenerationMethod::Grow,
+                    1,
+                    config.subtree_depth,
+                    rng,
+                )?
+            }
+            MutationOperator::Hoist => *function = take(node),
+            MutationOperator::Shrink => *node = ComputeFunction::random(&[1.0, 0.0, 0.0], rng)?,
+            MutationOperator::Constant => {
+                if let ComputeFunction::Zero(c) = node {
+                    if let ConstantFunction::Constant(r, g, b) = &mut **c {
+                        for channel in [r, g, b] {
+                            *channel += rng.gen_range(-1.0..=1.0) * config.constant_scale;
+                        }
+                    }
+                }
+            }
+            MutationOperator::Coord => {
+                if let ComputeFunction::Zero(c) = node {
+                    if let ConstantFunction::Coord(dim) = &mut **c {
+                        let current = *dim;
+                        // Can unwrap because there are always two other coordinates
+                        *dim = (0..3).filter(|&d| d != current).choose(rng).unwrap();
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Replaces the operator at `node` with a different one of the same arity
+fn point_mutate<R: Rng + ?Sized>(
+    node: &mut ComputeFunction,
+    rng: &mut R,
+) -> Result<(), ApplicationError> {
+    match node {
+        ComputeFunction::Zero(_) => *node = ComputeFunction::random(&[1.0, 0.0, 0.0], rng)?,
+        ComputeFunction::One(inner) => {
+            let mut replacement = SingleArgFunction::iter()
+                .filter(|f| discriminant(f) != discriminant(&**inner))
+                .collect::<Vec<_>>()
+                .choose(rng)
+                .ok_or(ApplicationError::BadArg)?
+                .clone();
+            for (new, old) in replacement.args_mut().zip(inner.args_mut()) {
+                *new = take(old);
+            }
+            **inner = replacement;
+        }
+        ComputeFunction::Two(inner) => {
+            let mut replacement = TwoArgFunction::iter()
+                .filter(|f| discriminant(f) != discriminant(&**inner))
+                .collect::<Vec<_>>()
+                .choose(rng)
+                .ok_or(ApplicationError::BadArg)?
+                .clone();
+            for (new, old) in replacement.args_mut().zip(inner.args_mut()) {
+                *new = take(old);
+            }
+            **inner = replacement;
+        }
+        ComputeFunction::Placeholder => {}
+    }
+    Ok(())
+}
+
+impl Gene {
+    /// Returns a mutated copy of self, trying each operator once with its configured probability
+    pub fn mutate<R: Rng + ?Sized>(
+        &self,
+        config: &MutationConfig,
+        rng: &mut R,
+    ) -> Result<Gene, ApplicationError> {
+        let mut gene = self.clone();
+        for operator in MutationOperator::iter() {
+            if rng.gen::<f32>() < config.probability(operator) {
+                operator.apply(&mut gene.function, config, rng)?;
+            }
+        }
+        Ok(gene)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use crate::{compute_functions::test_function, gene::GeneRng};
+
+    use super::*;
+
+    #[test]
+    fn test_operators() {
+        let mut rng = GeneRng::seed_from_u64(0);
+        let config = MutationConfig::default();
+        for operator in MutationOperator::iter() {
+            let mut function = test_function();
+            assert!(operator.apply(&mut function, &config, &mut rng).unwrap());
+            assert!(function
+                .bfs()
+                .iter()
+                .all(|(node, _)| !matches!(node, ComputeFunction::Placeholder)));
+        }
+        let mut leaf = ComputeFunction::Zero(Box::new(ConstantFunction::Coord(1)));
+        assert!(!MutationOperator::Hoist
+            .apply(&mut leaf, &config, &mut rng)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_point_keeps_arity() {
+        let mut rng = GeneRng::seed_from_u64(1);
+        for _ in 0..20 {
+            let mut function = test_function();
+            point_mutate(&mut function, &mut rng).unwrap();
+            match function {
+                ComputeFunction::Two(inner) => {
+                    assert!(!matches!(*inner, TwoArgFunction::Add(..)));
+                    assert!(matches!(inner.get_arg(0usize), ComputeFunction::One(_)));
+                }
+                _ => panic!("point mutation changed arity"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_mutate_seeded() {
+        let gene = Gene::new(0, test_function());
+        let config = MutationConfig::default();
+        let first = gene
+            .mutate(&config, &mut GeneRng::seed_from_u64(2))
+            .unwrap();
+        let second = gene
+            .mutate(&config, &mut GeneRng::seed_from_u64(2))
+            .unwrap();
+        assert_eq!(
+            bincode::serialize(&first).unwrap(),
+            bincode::serialize(&second).unwrap()
+        );
+    }
+}
@@ -0,0 +1,109 @@
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// Limits requested from the device
+pub enum LimitsPolicy {
+    /// Limits every adapter supports, including downlevel ones such as webgl2 and software rasterisers
+    #[default]
+    Downlevel,
+    /// Everything the chosen adapter supports, allowing larger buffers and fewer tiles
+    Adapter,
+    Custom(wgpu::Limits),
+}
+
+impl LimitsPolicy {
+    pub fn limits(&self, adapter: &wgpu::Adapter) -> wgpu::Limits {
+        match self {
+            LimitsPolicy::Downlevel => wgpu::Limits::downlevel_defaults(),
+            LimitsPolicy::Adapter => adapter.limits(),
+            LimitsPolicy::Custom(limits) => limits.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// How `GpuInstance::with_config` chooses an adapter and sets up its device
+///
+/// Defaults to any backend and any adapter, with downlevel limits and no features
+pub struct GpuConfig {
+    backends: wgpu::Backends,
+    power_preference: wgpu::PowerPreference,
+    force_fallback_adapter: bool,
+    limits: LimitsPolicy,
+    features: wgpu::Features,
+}
+
+impl Default for GpuConfig {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+            limits: LimitsPolicy::default(),
+            features: wgpu::Features::empty(),
+        }
+    }
+}
+
+impl GpuConfig {
+    /// Backends adapters may come from
+    pub fn backends(mut self, backends: wgpu::Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    /// Only accept a fallback adapter, i.e. a software rasteriser such as lavapipe or warp
+    pub fn force_fallback_adapter(mut self, force_fallback_adapter: bool) -> Self {
+        self.force_fallback_adapter = force_fallback_adapter;
+        self
+    }
+
+    pub fn limits(mut self, limits: LimitsPolicy) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Features the device must have, failing with `GpuError::UnsupportedFeatures` if the adapter lacks any
+    pub fn features(mut self, features: wgpu::Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: self.backends,
+            ..Default::default()
+        })
+    }
+
+    pub fn adapter_options(&self) -> wgpu::RequestAdapterOptions<'static, 'static> {
+        wgpu::RequestAdapterOptions {
+            power_preference: self.power_preference,
+            force_fallback_adapter: self.force_fallback_adapter,
+            compatible_surface: None,
+        }
+    }
+
+    pub fn limits_policy(&self) -> &LimitsPolicy {
+        &self.limits
+    }
+
+    pub fn required_features(&self) -> wgpu::Features {
+        self.features
+    }
+
+    /// Describes every adapter on the configured backends, whether or not it would be chosen
+    ///
+    /// On the gl backend this should be called before any `GpuInstance` is created, as dropping the enumerated
+    /// adapters can invalidate existing gl devices
+    pub fn available_adapters(&self) -> Vec<wgpu::AdapterInfo> {
+        self.instance()
+            .enumerate_adapters(self.backends)
+            .iter()
+            .map(|adapter| adapter.get_info())
+            .collect()
+    }
+}
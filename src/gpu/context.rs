@@ -0,0 +1,454 @@
+use std::{borrow::Cow, sync::Arc};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    compute_functions::{
+        evaluate::Rgb,
+        image::{ImageConfig, Resolution},
+        shader_builder::{pack_constants, ConstantMode, CONSTANTS_BINDING, FRAME_WORKGROUP_SIZE},
+    },
+    error::RenderError,
+    gene::Gene,
+};
+
+use super::{
+    instance::{lock, GpuInstance},
+    pipeline_cache::FunctionKey,
+};
+
+/// Bytes of output stored per pixel, one f32 for each channel
+pub const BYTES_PER_PIXEL: u64 = 12;
+
+/// Output buffers sized for up to `pixels` pixels
+struct FrameBuffers {
+    pixels: u64,
+    output: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// A gene compiled for the gpu, which renders any number of frames without recompiling
+///
+/// Bounds and resolution are uploaded with buffer writes for each frame, and output buffers are only
+/// reallocated when a frame has more pixels than any before it.
+/// Genes prepared with `ConstantMode::Buffer` can also have their constants changed with buffer writes
+pub struct PreparedGene<'a> {
+    gpu: &'a GpuInstance,
+    gene: Gene,
+    pipeline: Arc<wgpu::ComputePipeline>,
+    resolution: wgpu::Buffer,
+    bounds: wgpu::Buffer,
+    /// Buffer and count of constants, in `ConstantMode::Buffer`
+    constants: Option<(wgpu::Buffer, usize)>,
+    frame: Option<FrameBuffers>,
+}
+
+impl GpuInstance {
+    /// Compiles `gene` once so it can be rendered repeatedly with `PreparedGene::render`
+    ///
+    /// Pipelines are cached by the structure of the gene's function, so structurally identical genes
+    /// are only compiled once
+    pub async fn prepare(&self, gene: &Gene) -> Result<PreparedGene<'_>, RenderError> {
+        self.prepare_with(gene, ConstantMode::Inline).await
+    }
+
+    /// Like `prepare`, compiling constants according to `constant_mode`
+    ///
+    /// With `ConstantMode::Buffer`, genes that differ only in their constants share a pipeline
+    pub async fn prepare_with(
+        &self,
+        gene: &Gene,
+        constant_mode: ConstantMode,
+    ) -> Result<PreparedGene<'_>, RenderError> {
+        self.check_lost()?;
+        if gene.function().has_placeholder() {
+            return Err(RenderError::Placeholder);
+        }
+        let (cache, key) = match constant_mode {
+            ConstantMode::Inline => (&self.pipelines, FunctionKey::new(gene.function())),
+            ConstantMode::Buffer => (
+                &self.buffered_pipelines,
+                FunctionKey::new(&gene.function().without_constants()),
+            ),
+        };
+        let cached = lock(cache).get(&key);
+        let pipeline = match cached {
+            Some(pipeline) => pipeline,
+            None => {
+                let pipeline = Arc::new(self.compile(gene, constant_mode).await?);
+                lock(cache).insert(key, pipeline.clone());
+                pipeline
+            }
+        };
+        let storage = |label, size| {
+            self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        let (resolution, bounds, constants) = self
+            .error_scope(|| {
+                let constants = (constant_mode == ConstantMode::Buffer).then(|| {
+                    let values = gene.function().constants();
+                    let buffer =
+                        self.device
+                            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                                label: Some("constants"),
+                                contents: bytemuck::cast_slice(&pack_constants(&values)),
+                                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                            });
+                    (buffer, values.len())
+                });
+                // Sizes match the bincode encodings of `Resolution` and `Bounds`
+                (storage("resolution", 8), storage("bounds", 20), constants)
+            })
+            .await?;
+        Ok(PreparedGene {
+            gpu: self,
+            gene: gene.clone(),
+            pipeline,
+            resolution,
+            bounds,
+            constants,
+            frame: None,
+        })
+    }
+
+    /// Builds the pipeline for a gene, without consulting the cache
+    async fn compile(
+        &self,
+        gene: &Gene,
+        constant_mode: ConstantMode,
+    ) -> Result<wgpu::ComputePipeline, RenderError> {
+        let shader_code = gene.shader_code(constant_mode)?;
+        self.error_scope(|| {
+            let shader_module = self
+                .device
+                .create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: None,
+                    source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(&shader_code)),
+                });
+            self.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: None,
+                    layout: None,
+                    module: &shader_module,
+                    entry_point: "main",
+                })
+        })
+        .await
+    }
+}
+
+impl PreparedGene<'_> {
+    pub fn gene(&self) -> &Gene {
+        &self.gene
+    }
+
+    /// Replaces the gene's constants, in the order of `ComputeFunction::constants`, without recompiling
+    ///
+    /// Only genes prepared with `ConstantMode::Buffer` have constants that can be set
+    pub fn set_constants(&mut self, values: &[Rgb]) -> Result<(), RenderError> {
+        let expected = self.constants.as_ref().map_or(0, |(_, count)| *count);
+        if values.len() != expected {
+            return Err(RenderError::ConstantCount {
+                expected,
+                found: values.len(),
+            });
+        }
+        if let Some((buffer, _)) = &self.constants {
+            self.gpu
+                .queue
+                .write_buffer(buffer, 0, bytemuck::cast_slice(&pack_constants(values)));
+            let mut function = self.gene.function().clone();
+            function.set_constants(values);
+            self.gene = self.gene.with_function(function);
+        }
+        Ok(())
+    }
+
+    /// Switches to a gene that differs only in its constants, such as after a constant mutation
+    pub fn set_gene(&mut self, gene: &Gene) -> Result<(), RenderError> {
+        if gene.function().without_constants() != self.gene.function().without_constants() {
+            return Err(RenderError::StructureMismatch);
+        }
+        self.set_constants(&gene.function().constants())?;
+        self.gene = gene.clone();
+        Ok(())
+    }
+
+    /// Renders a frame with the same layout as `GpuInstance::generate_buffer`
+    pub async fn render(&mut self, image_config: &ImageConfig) -> Result<Vec<f32>, RenderError> {
+        let gpu = self.gpu;
+        gpu.check_lost()?;
+        let size = gpu
+            .error_scope(|| {
+                let mut encoder = gpu
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+                let size = self.encode(&mut encoder, image_config)?;
+                gpu.queue.submit(Some(encoder.finish()));
+                Ok::<_, RenderError>(size)
+            })
+            .await
+            .and_then(|size| size)
+            .inspect_err(|_| self.discard_frame())?;
+        gpu.read_buffer(self.staging(), size).await
+    }
+
+    /// Drops the frame buffers, so buffers created in a failed error scope are not reused by later frames
+    pub(crate) fn discard_frame(&mut self) {
+        self.frame = None;
+    }
+
+    /// Uploads the frame's arguments and records its compute pass and copy to the staging buffer into `encoder`,
+    /// returning the number of bytes copied
+    pub(crate) fn encode(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        image_config: &ImageConfig,
+    ) -> Result<u64, RenderError> {
+        let queue = &self.gpu.queue;
+        queue.write_buffer(
+            &self.resolution,
+            0,
+            &bincode::serialize(&image_config.resolution)?,
+        );
+        queue.write_buffer(&self.bounds, 0, &bincode::serialize(&image_config.bounds)?);
+
+        let pixels = image_config.pixels() as u64;
+        if self
+            .frame
+            .as_ref()
+            .is_none_or(|frame| frame.pixels < pixels)
+        {
+            self.frame = Some(self.frame_buffers(pixels));
+        }
+        // Can unwrap because the frame buffers were just created if missing
+        let frame = self.frame.as_ref().unwrap();
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, &frame.bind_group, &[]);
+            cpass.insert_debug_marker("compute buffer");
+            let Resolution(width, height) = image_config.resolution;
+            let [group_width, group_height, _] = FRAME_WORKGROUP_SIZE;
+            cpass.dispatch_workgroups(
+                width.div_ceil(group_width),
+                height.div_ceil(group_height),
+                1,
+            );
+        }
+        let size = pixels * BYTES_PER_PIXEL;
+        encoder.copy_buffer_to_buffer(&frame.output, 0, &frame.staging, 0, size);
+        Ok(size)
+    }
+
+    /// Buffer the last encoded frame was copied to
+    ///
+    /// Panics if no frame has been encoded
+    pub(crate) fn staging(&self) -> &wgpu::Buffer {
+        &self
+            .frame
+            .as_ref()
+            .expect("a frame should be encoded before reading it")
+            .staging
+    }
+
+    fn frame_buffers(&self, pixels: u64) -> FrameBuffers {
+        let device = &self.gpu.device;
+        let size = pixels * BYTES_PER_PIXEL;
+        let output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("output"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: output.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: self.resolution.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: self.bounds.as_entire_binding(),
+            },
+        ];
+        if let Some((constants, _)) = &self.constants {
+            entries.push(wgpu::BindGroupEntry {
+                binding: CONSTANTS_BINDING,
+                resource: constants.as_entire_binding(),
+            });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+        FrameBuffers {
+            pixels,
+            output,
+            staging,
+            bind_group,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::block_on;
+
+    use crate::{compute_functions::image::Bounds, gpu::pipeline_cache::CacheStats};
+
+    use super::*;
+
+    #[test]
+    fn test_frames_match_single_renders() {
+        let gene = Gene::new(0, "sin(x * y) * rgb(1, 0.5, 2) + z".parse().unwrap());
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        let mut prepared = block_on(gpu.prepare(&gene)).unwrap();
+        // Zooming in, then changing resolution both up and down
+        let frames = (0..20)
+            .map(|i| {
+                (
+                    Resolution::new(16, 12),
+                    Bounds::new(0.1 * i as f32, 0.0, 0.5, 4.0 / (i + 1) as f32, 3.0),
+                )
+            })
+            .chain([
+                (
+                    Resolution::new(40, 31),
+                    Bounds::new(-1.0, -1.0, 0.0, 2.0, 2.0),
+                ),
+                (
+                    Resolution::new(3, 5),
+                    Bounds::new(-1.0, -1.0, 0.0, 2.0, 2.0),
+                ),
+            ]);
+        for (resolution, bounds) in frames {
+            let config = ImageConfig { resolution, bounds };
+            let frame = block_on(prepared.render(&config)).unwrap();
+            let expected = block_on(gpu.generate_buffer(&config, &gene)).unwrap();
+            assert_eq!(frame, expected);
+        }
+    }
+
+    #[test]
+    fn test_failed_frame_is_not_reused() {
+        let gene = Gene::new(0, "x * y".parse().unwrap());
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        let mut prepared = block_on(gpu.prepare(&gene)).unwrap();
+        let bounds = Bounds::new(-1.0, -1.0, 0.0, 2.0, 2.0);
+        // Frame buffers this large exceed the device's buffer size limit
+        let oversized = ImageConfig {
+            resolution: Resolution::new(1 << 15, 1 << 15),
+            bounds,
+        };
+        assert!(matches!(
+            block_on(prepared.render(&oversized)),
+            Err(RenderError::Validation(_))
+        ));
+        let config = ImageConfig {
+            resolution: Resolution::new(8, 8),
+            bounds,
+        };
+        assert_eq!(
+            block_on(prepared.render(&config)).unwrap(),
+            block_on(gpu.generate_buffer(&config, &gene)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_pipelines_are_cached() {
+        let config = ImageConfig {
+            resolution: Resolution::new(8, 8),
+            bounds: Bounds::new(0.0, 0.0, 0.0, 1.0, 1.0),
+        };
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        let elite = Gene::new(0, "cos(x) * y".parse().unwrap());
+        let first = block_on(gpu.generate_buffer(&config, &elite)).unwrap();
+        // A surviving elite, and a different gene with the same function
+        let second = block_on(gpu.generate_buffer(&config, &elite)).unwrap();
+        let clone = Gene::new(9, "cos(x) * y".parse().unwrap());
+        block_on(gpu.generate_buffer(&config, &clone)).unwrap();
+        block_on(gpu.generate_buffer(&config, &Gene::new(0, "x".parse().unwrap()))).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(
+            gpu.pipeline_cache_stats(),
+            CacheStats { hits: 2, misses: 2 }
+        );
+    }
+
+    #[test]
+    fn test_constants_update_without_recompiling() {
+        let config = ImageConfig {
+            resolution: Resolution::new(9, 7),
+            bounds: Bounds::new(-1.0, -1.0, 0.0, 2.0, 2.0),
+        };
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        let gene = Gene::new(
+            0,
+            "sin(x * rgb(1, 2, 3)) + y * rgb(0.5, 0.5, 0.5)"
+                .parse()
+                .unwrap(),
+        );
+        let mut prepared = block_on(gpu.prepare_with(&gene, ConstantMode::Buffer)).unwrap();
+        assert_eq!(
+            block_on(prepared.render(&config)).unwrap(),
+            block_on(gpu.generate_buffer(&config, &gene)).unwrap()
+        );
+
+        let stats = gpu.pipeline_cache_stats();
+        let values = [[-3.0, 0.25, 7.0], [1.0, -1.0, 2.0]];
+        prepared.set_constants(&values).unwrap();
+        let tuned = Gene::new(
+            0,
+            "sin(x * rgb(-3, 0.25, 7)) + y * rgb(1, -1, 2)"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(prepared.gene().function(), tuned.function());
+        let frame = block_on(prepared.render(&config)).unwrap();
+        // Preparing another gene with the same structure reuses the pipeline
+        let mut other = block_on(gpu.prepare_with(&tuned, ConstantMode::Buffer)).unwrap();
+        assert_eq!(gpu.pipeline_cache_stats().misses, stats.misses);
+        assert_eq!(frame, block_on(other.render(&config)).unwrap());
+        assert_eq!(
+            frame,
+            block_on(gpu.generate_buffer(&config, &tuned)).unwrap()
+        );
+
+        assert!(matches!(
+            prepared.set_constants(&values[..1]),
+            Err(RenderError::ConstantCount {
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            other.set_gene(&Gene::new(0, "x".parse().unwrap())),
+            Err(RenderError::StructureMismatch)
+        ));
+        other.set_gene(&gene).unwrap();
+        assert_eq!(
+            block_on(other.render(&config)).unwrap(),
+            block_on(gpu.generate_buffer(&config, &gene)).unwrap()
+        );
+    }
+}
@@ -1,53 +1,155 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
 use log::debug;
 
-use crate::error::GpuError;
+use crate::error::{GpuError, RenderError};
+
+use super::{
+    config::GpuConfig,
+    pipeline_cache::{CacheStats, FunctionCache, DEFAULT_PIPELINE_CACHE_CAPACITY},
+};
+
+/// Locks one of the mutexes shared by the gpu module
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // Can unwrap because the lock is never held across a panic
+    mutex.lock().unwrap()
+}
 
 #[derive(Debug)]
 pub struct GpuInstance {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
+    pub adapter_info: wgpu::AdapterInfo,
+    /// Reason the device was lost, set from wgpu's device lost callback
+    lost: Arc<Mutex<Option<String>>>,
+    /// Compiled pipelines of recently prepared functions
+    pub(crate) pipelines: Mutex<FunctionCache<Arc<wgpu::ComputePipeline>>>,
+    /// Compiled `ConstantMode::Buffer` pipelines, keyed by functions with their constants zeroed
+    pub(crate) buffered_pipelines: Mutex<FunctionCache<Arc<wgpu::ComputePipeline>>>,
+    /// Bytecode interpreter pipeline, compiled on first use
+    pub(crate) interpreter: Mutex<Option<Arc<wgpu::ComputePipeline>>>,
 }
 
 impl GpuInstance {
+    /// Connects to the default adapter, see `GpuConfig::default`
     pub async fn new() -> Result<Self, GpuError> {
+        Self::with_config(&GpuConfig::default()).await
+    }
+
+    pub async fn with_config(config: &GpuConfig) -> Result<Self, GpuError> {
         // Instantiates instance of WebGPU
-        let instance = wgpu::Instance::default();
+        let instance = config.instance();
 
         // `request_adapter` instantiates the general connection to the GPU
         let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .request_adapter(&config.adapter_options())
             .await
             .ok_or(GpuError::NoAdapter)?;
 
+        let missing = config.required_features() - adapter.features();
+        if !missing.is_empty() {
+            return Err(GpuError::UnsupportedFeatures(missing));
+        }
+
         // `request_device` instantiates the feature specific connection to the GPU, defining some parameters,
         //  `features` being the available features.
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::downlevel_defaults(),
+                    required_features: config.required_features(),
+                    required_limits: config.limits_policy().limits(&adapter),
                 },
                 None,
             )
             .await
-            .unwrap();
+            .map_err(GpuError::RequestDeviceError)?;
+
+        let lost = Arc::new(Mutex::new(None));
+        let callback_lost = lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            // The callback also runs when the instance is dropped, which is not a loss
+            if !matches!(reason, wgpu::DeviceLostReason::Dropped) {
+                *lock(&callback_lost) = Some(format!("{:?}: {}", reason, message));
+            }
+        });
 
-        let result = Self { device, queue };
+        let result = Self {
+            device,
+            queue,
+            adapter_info: adapter.get_info(),
+            lost,
+            pipelines: Mutex::new(FunctionCache::new(DEFAULT_PIPELINE_CACHE_CAPACITY)),
+            buffered_pipelines: Mutex::new(FunctionCache::new(DEFAULT_PIPELINE_CACHE_CAPACITY)),
+            interpreter: Mutex::new(None),
+        };
         debug!("got gpu: {:?}", &result);
         Ok(result)
     }
+
+    /// Errors if the device has been lost, in which case a new instance is needed to keep rendering
+    pub fn check_lost(&self) -> Result<(), RenderError> {
+        match lock(&self.lost).as_ref() {
+            Some(reason) => Err(RenderError::DeviceLost(reason.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Hits and misses of the compiled pipeline cache so far
+    pub fn pipeline_cache_stats(&self) -> CacheStats {
+        let inline = lock(&self.pipelines).stats();
+        let buffered = lock(&self.buffered_pipelines).stats();
+        CacheStats {
+            hits: inline.hits + buffered.hits,
+            misses: inline.misses + buffered.misses,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use pollster::block_on;
 
+    use crate::gpu::config::LimitsPolicy;
+
     use super::*;
 
     #[test]
     fn create_gpu() {
         let gpu = block_on(GpuInstance::new()).unwrap();
         println!("{:?}", gpu);
+        assert!(gpu.check_lost().is_ok());
+    }
+
+    #[test]
+    fn test_config() {
+        let config = GpuConfig::default();
+        let adapters = config.available_adapters();
+        assert!(!adapters.is_empty());
+
+        let config = config.limits(LimitsPolicy::Adapter);
+        let gpu = block_on(GpuInstance::with_config(&config)).unwrap();
+        assert!(adapters.contains(&gpu.adapter_info));
+        assert!(
+            gpu.device.limits().max_storage_buffer_binding_size
+                >= wgpu::Limits::downlevel_defaults().max_storage_buffer_binding_size
+        );
+        // Wgpu's gl backend loses the display of live devices when an unused adapter is dropped
+        drop(gpu);
+
+        let config = GpuConfig::default().features(wgpu::Features::all());
+        assert!(matches!(
+            block_on(GpuInstance::with_config(&config)),
+            Err(GpuError::UnsupportedFeatures(_))
+        ));
+
+        let config = GpuConfig::default().force_fallback_adapter(true);
+        match block_on(GpuInstance::with_config(&config)) {
+            Ok(gpu) => assert_eq!(gpu.adapter_info.device_type, wgpu::DeviceType::Cpu),
+            Err(error) => assert!(matches!(error, GpuError::NoAdapter)),
+        }
+
+        let config = GpuConfig::default().backends(wgpu::Backends::empty());
+        assert!(config.available_adapters().is_empty());
     }
 }
@@ -0,0 +1,232 @@
+use std::{borrow::Cow, sync::Arc};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    compute_functions::{
+        bytecode::interpreter_shader,
+        image::{ImageConfig, Resolution},
+        shader_builder::FRAME_WORKGROUP_SIZE,
+    },
+    error::{BytecodeError, RenderError},
+    gene::Gene,
+};
+
+use super::{
+    context::BYTES_PER_PIXEL,
+    instance::{lock, GpuInstance},
+};
+
+impl GpuInstance {
+    /// Renders every gene with the bytecode interpreter, with no pipeline created per gene
+    ///
+    /// Genes are rendered in as few dispatches as the device's buffer limits allow. Each result has the
+    /// layout of `generate_buffer`, or is the reason that gene could not be interpreted.
+    ///
+    /// Experimental: no backend renders with it yet, and frames are not tiled, so a frame larger than one
+    /// storage binding fails where `generate_tiled_buffer` would succeed
+    pub async fn interpret_batch(
+        &self,
+        image_config: &ImageConfig,
+        genes: &[Gene],
+    ) -> Result<Vec<Result<Vec<f32>, BytecodeError>>, RenderError> {
+        self.check_lost()?;
+        let programs: Vec<_> = genes
+            .iter()
+            .map(|gene| gene.function().bytecode())
+            .collect();
+        if image_config.pixels() == 0 {
+            return Ok(programs
+                .into_iter()
+                .map(|program| program.map(|_| vec![]))
+                .collect());
+        }
+        let limits = self.device.limits();
+        let max_bytes = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        let image_bytes = image_config.pixels() as u64 * BYTES_PER_PIXEL;
+        let per_dispatch = (max_bytes / image_bytes.max(1))
+            .min(limits.max_compute_workgroups_per_dimension as u64)
+            .max(1) as usize;
+
+        let pipeline = self.interpreter_pipeline().await?;
+        let mut results = Vec::with_capacity(genes.len());
+        for chunk in programs.chunks(per_dispatch) {
+            let buffer = self.interpret(&pipeline, image_config, chunk).await?;
+            let images = buffer.chunks_exact(image_config.pixels() as usize * 3);
+            for (program, image) in chunk.iter().zip(images) {
+                results.push(match program {
+                    Ok(_) => Ok(image.to_vec()),
+                    Err(error) => Err(error.clone()),
+                });
+            }
+        }
+        Ok(results)
+    }
+
+    async fn interpreter_pipeline(&self) -> Result<Arc<wgpu::ComputePipeline>, RenderError> {
+        if let Some(pipeline) = lock(&self.interpreter).as_ref() {
+            return Ok(pipeline.clone());
+        }
+        let shader_code = interpreter_shader();
+        let pipeline = self
+            .error_scope(|| {
+                let shader_module =
+                    self.device
+                        .create_shader_module(wgpu::ShaderModuleDescriptor {
+                            label: Some("interpreter"),
+                            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(&shader_code)),
+                        });
+                self.device
+                    .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("interpreter"),
+                        layout: None,
+                        module: &shader_module,
+                        entry_point: "main",
+                    })
+            })
+            .await?;
+        let pipeline = Arc::new(pipeline);
+        *lock(&self.interpreter) = Some(pipeline.clone());
+        Ok(pipeline)
+    }
+
+    /// Renders one dispatch of programs, each image following the last. Failed programs render nothing
+    async fn interpret(
+        &self,
+        pipeline: &wgpu::ComputePipeline,
+        image_config: &ImageConfig,
+        programs: &[Result<Vec<u32>, BytecodeError>],
+    ) -> Result<Vec<f32>, RenderError> {
+        // A header of program ranges, then the programs
+        let mut code = vec![0; programs.len() * 2];
+        for (i, program) in programs.iter().enumerate() {
+            code[i * 2] = code.len() as u32;
+            if let Ok(program) = program {
+                code.extend(program);
+            }
+            code[i * 2 + 1] = code.len() as u32;
+        }
+        let resolution = bincode::serialize(&image_config.resolution)?;
+        let bounds = bincode::serialize(&image_config.bounds)?;
+        let size = image_config.pixels() as u64 * BYTES_PER_PIXEL * programs.len() as u64;
+
+        let staging = self
+            .error_scope(|| {
+                let init = |label, contents: &[u8]| {
+                    self.device
+                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                            label: Some(label),
+                            contents,
+                            usage: wgpu::BufferUsages::STORAGE,
+                        })
+                };
+                let resolution = init("resolution", &resolution);
+                let bounds = init("bounds", &bounds);
+                let code = init("code", bytemuck::cast_slice(&code));
+                let output = self.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("output"),
+                    size,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                });
+                let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("staging"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                let entries: Vec<_> = [&output, &resolution, &bounds, &code]
+                    .iter()
+                    .enumerate()
+                    .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                        binding: binding as u32,
+                        resource: buffer.as_entire_binding(),
+                    })
+                    .collect();
+                let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &pipeline.get_bind_group_layout(0),
+                    entries: &entries,
+                });
+
+                let mut encoder = self
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+                {
+                    let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                        label: None,
+                        timestamp_writes: None,
+                    });
+                    cpass.set_pipeline(pipeline);
+                    cpass.set_bind_group(0, &bind_group, &[]);
+                    let Resolution(width, height) = image_config.resolution;
+                    let [group_width, group_height, _] = FRAME_WORKGROUP_SIZE;
+                    cpass.dispatch_workgroups(
+                        width.div_ceil(group_width),
+                        height.div_ceil(group_height),
+                        programs.len() as u32,
+                    );
+                }
+                encoder.copy_buffer_to_buffer(&output, 0, &staging, 0, size);
+                self.queue.submit(Some(encoder.finish()));
+                staging
+            })
+            .await?;
+        self.read_buffer(&staging, size).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::block_on;
+    use rand::SeedableRng;
+
+    use crate::{
+        compute_functions::{
+            image::Bounds, test_utils::assert_close, utils::GenerationMethod, ComputeFunction,
+        },
+        gene::GeneRng,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_matches_compiled_shaders() {
+        let config = ImageConfig {
+            resolution: Resolution::new(13, 9),
+            bounds: Bounds::new(-2.0, -1.5, 0.5, 4.0, 3.0),
+        };
+        let mut rng = GeneRng::seed_from_u64(0);
+        let mut genes: Vec<_> = (0..40)
+            .map(|seed| {
+                let function =
+                    ComputeFunction::random_deep(GenerationMethod::Grow, 1, 6, &mut rng).unwrap();
+                Gene::new(seed, function)
+            })
+            .collect();
+        genes.push(Gene::new(0, "sin(_)".parse().unwrap()));
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        let results = block_on(gpu.interpret_batch(&config, &genes)).unwrap();
+        assert_eq!(results.len(), genes.len());
+        assert_eq!(results[40], Err(BytecodeError::Placeholder));
+        for (gene, result) in genes.iter().zip(&results[..40]) {
+            let expected = block_on(gpu.generate_buffer(&config, gene)).unwrap();
+            assert_close(gene.function(), result.as_ref().unwrap(), &expected, 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_empty_frame() {
+        let config = ImageConfig {
+            resolution: Resolution::new(0, 4),
+            bounds: Bounds::new(0.0, 0.0, 0.0, 1.0, 1.0),
+        };
+        let genes = [
+            Gene::new(0, "x + y".parse().unwrap()),
+            Gene::new(1, "sin(_)".parse().unwrap()),
+        ];
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        let results = block_on(gpu.interpret_batch(&config, &genes)).unwrap();
+        assert_eq!(results, [Ok(vec![]), Err(BytecodeError::Placeholder)]);
+    }
+}
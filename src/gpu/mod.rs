@@ -1,2 +1,7 @@
+pub mod config;
+pub mod context;
 pub mod instance;
+pub mod interpreter;
+pub mod pipeline_cache;
 pub mod processing;
+pub mod tiling;
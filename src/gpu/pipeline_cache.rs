@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use crate::compute_functions::ComputeFunction;
+
+/// Pipelines kept by a `GpuInstance` before the least recently used is evicted
+pub const DEFAULT_PIPELINE_CACHE_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Identifies a function by its serialised structure, compared bitwise so NaN constants match themselves
+pub struct FunctionKey {
+    hash: u64,
+    bytes: Vec<u8>,
+}
+
+impl FunctionKey {
+    pub fn new(function: &ComputeFunction) -> Self {
+        Self {
+            hash: function.structural_hash(),
+            // Can unwrap because serialising to a vec cannot fail
+            bytes: bincode::serialize(function).unwrap(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    /// Kept to rule out hash collisions
+    bytes: Vec<u8>,
+    value: V,
+    last_used: u64,
+}
+
+#[derive(Debug)]
+/// Least recently used cache keyed by the structure of a function, used to reuse compiled pipelines
+pub struct FunctionCache<V> {
+    capacity: usize,
+    entries: HashMap<u64, Entry<V>>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl<V: Clone> FunctionCache<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Looks up a structurally identical function, counting a hit or miss
+    pub fn get(&mut self, key: &FunctionKey) -> Option<V> {
+        self.tick += 1;
+        match self.entries.get_mut(&key.hash) {
+            Some(entry) if entry.bytes == key.bytes => {
+                entry.last_used = self.tick;
+                self.stats.hits += 1;
+                Some(entry.value.clone())
+            }
+            _ => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Stores `value` for the function of `key`, evicting the least recently used entry if full
+    pub fn insert(&mut self, key: FunctionKey, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let FunctionKey { hash, bytes } = key;
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&hash) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(hash, _)| *hash);
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(
+            hash,
+            Entry {
+                bytes,
+                value,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compute_functions::ConstantFunction;
+
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let parse = |s: &str| FunctionKey::new(&s.parse().unwrap());
+        let mut cache = FunctionCache::new(2);
+        cache.insert(parse("x"), 0);
+        cache.insert(parse("y"), 1);
+        assert_eq!(cache.get(&parse("x")), Some(0));
+        cache.insert(parse("z"), 2);
+        assert_eq!(cache.get(&parse("y")), None);
+        assert_eq!(cache.get(&parse("x")), Some(0));
+        assert_eq!(cache.get(&parse("z")), Some(2));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 1 });
+    }
+
+    #[test]
+    fn test_nan_constants_hit() {
+        let nan = || {
+            FunctionKey::new(&ComputeFunction::Zero(Box::new(
+                ConstantFunction::Constant(f32::NAN, 0.0, 1.0),
+            )))
+        };
+        let mut cache = FunctionCache::new(2);
+        cache.insert(nan(), 0);
+        assert_eq!(cache.get(&nan()), Some(0));
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 0 });
+    }
+}
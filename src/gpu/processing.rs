@@ -1,137 +1,152 @@
-use std::borrow::Cow;
+use crate::{compute_functions::image::ImageConfig, error::RenderError, gene::Gene};
 
-use wgpu::util::DeviceExt;
-
-use crate::compute_functions::{image::ImageConfig, shader::ShaderFunction};
-
-use super::instance::GpuInstance;
+use super::{context::PreparedGene, instance::GpuInstance};
 
 impl GpuInstance {
+    /// Renders `gene` into an interleaved rgb buffer, row by row
+    ///
+    /// The shader is validated first, so an invalid gene returns an error rather than panicking inside wgpu.
+    /// Gpu errors raised while rendering are caught with error scopes and returned.
+    /// To render one gene repeatedly, use `prepare` to avoid recompiling it each time
     pub async fn generate_buffer(
         &self,
         image_config: &ImageConfig,
-        function: &dyn ShaderFunction,
-    ) -> Option<Vec<f32>> {
-        // Create shader
-        let shader_code = function.get_shader_code();
-        let shader_module = self
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: None,
-                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(&shader_code)),
-            });
+        gene: &Gene,
+    ) -> Result<Vec<f32>, RenderError> {
+        self.prepare(gene).await?.render(image_config).await
+    }
 
-        // Create arg buffers
-        let bounds_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("bounds"),
-                contents: &bincode::serialize(&image_config.bounds).unwrap(),
-                usage: wgpu::BufferUsages::STORAGE, // | wgpu::BufferUsages::COPY_DST
-                                                    // | wgpu::BufferUsages::COPY_SRC,
-            });
-        let resolution_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("resolution"),
-                contents: &bincode::serialize(&image_config.resolution).unwrap(),
-                usage: wgpu::BufferUsages::STORAGE, // | wgpu::BufferUsages::COPY_DST
-                                                    // | wgpu::BufferUsages::COPY_SRC,
-            });
+    /// Runs `f` inside out-of-memory and validation error scopes, returning the error if either caught one
+    pub(crate) async fn error_scope<T>(&self, f: impl FnOnce() -> T) -> Result<T, RenderError> {
+        self.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let result = f();
+        // Scopes are popped innermost first
+        let validation = self.device.pop_error_scope().await;
+        let out_of_memory = self.device.pop_error_scope().await;
+        match out_of_memory.or(validation) {
+            Some(error) => Err(error.into()),
+            None => Ok(result),
+        }
+    }
 
-        let pixels = image_config.resolution.0 * image_config.resolution.1;
-        let buffer_size = pixels * 4 * 3;
-        // Create output buffer
-        let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: buffer_size as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
-            mapped_at_creation: false,
-        });
-
-        // Create read buffer
-        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: buffer_size as u64,
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        let compute_pipeline =
-            self.device
-                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                    label: None,
-                    layout: None,
-                    module: &shader_module,
-                    entry_point: "main",
-                });
+    /// Maps the first `size` bytes of a `MAP_READ` buffer and copies them back as floats
+    pub(crate) async fn read_buffer(
+        &self,
+        buffer: &wgpu::Buffer,
+        size: u64,
+    ) -> Result<Vec<f32>, RenderError> {
+        // Can unwrap because one buffer gives one result
+        Ok(self.read_buffers(&[(buffer, size)]).await?.pop().unwrap())
+    }
 
-        // Instantiates the bind group, once again specifying the binding of buffers.
-        let bind_group_layout = compute_pipeline.get_bind_group_layout(0);
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: output_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: resolution_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: bounds_buffer.as_entire_binding(),
-                },
-            ],
-        });
-
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: None,
-                timestamp_writes: None,
-            });
-            cpass.set_pipeline(&compute_pipeline);
-            cpass.set_bind_group(0, &bind_group, &[]);
-            cpass.insert_debug_marker("compute buffer");
-            let groups = (pixels as f32 / 32.0).ceil();
-            println!("groups: {:?}", &groups);
-            cpass.dispatch_workgroups(groups as u32, 1, 1);
+    /// Like `read_buffer` for several buffers, mapping them all with a single wait on the device
+    pub(crate) async fn read_buffers(
+        &self,
+        buffers: &[(&wgpu::Buffer, u64)],
+    ) -> Result<Vec<Vec<f32>>, RenderError> {
+        let receivers: Vec<_> = buffers
+            .iter()
+            .map(|(buffer, size)| {
+                // Sets the buffer up for mapping, sending over the result of the mapping back to us when it is finished.
+                let (sender, receiver) = flume::bounded(1);
+                buffer
+                    .slice(..*size)
+                    .map_async(wgpu::MapMode::Read, move |v| {
+                        // The receiver is only dropped once this function has returned
+                        let _ = sender.send(v);
+                    });
+                receiver
+            })
+            .collect();
+        self.device.poll(wgpu::Maintain::wait());
+
+        let mut results = Vec::with_capacity(buffers.len());
+        for ((buffer, size), receiver) in buffers.iter().zip(receivers) {
+            match receiver.recv_async().await {
+                Ok(Ok(())) => {
+                    let data = buffer.slice(..*size).get_mapped_range();
+                    results.push(bytemuck::cast_slice(&data).to_vec());
+                    // All mapped views must be dropped before the buffer is unmapped
+                    drop(data);
+                    buffer.unmap();
+                }
+                Ok(Err(error)) => {
+                    self.check_lost()?;
+                    return Err(RenderError::BufferMap(error));
+                }
+                // The callback is dropped without being called when the device goes away
+                Err(_) => {
+                    self.check_lost()?;
+                    return Err(RenderError::DeviceLost(
+                        "buffer mapping was abandoned".to_string(),
+                    ));
+                }
+            }
         }
-        encoder.copy_buffer_to_buffer(&output_buffer, 0, &staging_buffer, 0, buffer_size as u64);
-        self.queue.submit(Some(encoder.finish()));
-        // Note that we're not calling `.await` here.
-        let buffer_slice = staging_buffer.slice(..);
-        // Sets the buffer up for mapping, sending over the result of the mapping back to us when it is finished.
-        let (sender, receiver) = flume::bounded(1);
-        buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
-        self.device.poll(wgpu::Maintain::wait()).panic_on_timeout();
-
-        // Awaits until `buffer_future` can be read from
-        if let Ok(Ok(())) = receiver.recv_async().await {
-            // Gets contents of buffer
-            let data = buffer_slice.get_mapped_range();
-            // Since contents are got in bytes, this converts these bytes back to u32
-            let result = bytemuck::cast_slice(&data).to_vec();
-
-            // With the current interface, we have to make sure all mapped views are
-            // dropped before we unmap the buffer.
-            drop(data);
-            staging_buffer.unmap(); // Unmaps buffer from memory
-                                    // If you are familiar with C++ these 2 lines can be thought of similarly to:
-                                    //   delete myPointer;
-                                    //   myPointer = NULL;
-                                    // It effectively frees the memory
-
-            // Returns data from buffer
-            Some(result)
-        } else {
-            panic!("failed to run compute on gpu!")
+        Ok(results)
+    }
+
+    /// Renders every gene with compiled shaders, recording all of them into one submission and reading the
+    /// results back with a single wait on the device
+    ///
+    /// Each result is the gene's buffer, or the error that stopped it being prepared. Errors affecting the
+    /// whole batch, such as device loss or a failed submission or readback, are returned instead
+    pub async fn render_batch(
+        &self,
+        genes: &[Gene],
+        image_config: &ImageConfig,
+    ) -> Result<Vec<Result<Vec<f32>, RenderError>>, RenderError> {
+        self.check_lost()?;
+        let mut prepared = Vec::with_capacity(genes.len());
+        for gene in genes {
+            prepared.push(match self.prepare(gene).await {
+                Err(error @ RenderError::DeviceLost(_)) => return Err(error),
+                result => result,
+            });
         }
+        self.render_prepared(prepared, image_config).await
+    }
+
+    /// Renders every successfully prepared gene in one submission, keeping the errors of the rest in their slots
+    async fn render_prepared(
+        &self,
+        mut prepared: Vec<Result<PreparedGene<'_>, RenderError>>,
+        image_config: &ImageConfig,
+    ) -> Result<Vec<Result<Vec<f32>, RenderError>>, RenderError> {
+        let sizes = self
+            .error_scope(|| {
+                let mut encoder = self
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+                let sizes = prepared
+                    .iter_mut()
+                    .flatten()
+                    .map(|prepared| prepared.encode(&mut encoder, image_config))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.queue.submit(Some(encoder.finish()));
+                Ok::<_, RenderError>(sizes)
+            })
+            .await
+            .and_then(|sizes| sizes)
+            .inspect_err(|_| {
+                for prepared in prepared.iter_mut().flatten() {
+                    prepared.discard_frame();
+                }
+            })?;
+
+        let staging: Vec<_> = prepared
+            .iter()
+            .flatten()
+            .map(|prepared| prepared.staging())
+            .zip(sizes)
+            .collect();
+        let mut buffers = self.read_buffers(&staging).await?.into_iter();
+        Ok(prepared
+            .into_iter()
+            // Can unwrap because every prepared gene was read
+            .map(|prepared| prepared.map(|_| buffers.next().unwrap()))
+            .collect())
     }
 }
 
@@ -143,6 +158,15 @@ mod tests {
         image::{Bounds, Resolution},
         ComputeFunction, ConstantFunction, SingleArgFunction,
     };
+    use rand::{seq::SliceRandom, SeedableRng};
+
+    use crate::{
+        compute_functions::{
+            shader_builder::ConstantMode, test_utils::assert_close, utils::GenerationMethod,
+        },
+        error::RenderError,
+        gene::GeneRng,
+    };
 
     use super::*;
 
@@ -155,7 +179,106 @@ mod tests {
             bounds: Bounds::new(0.0, 0.0, 0.0, 1.0, 1.0),
         };
         let gpu = block_on(GpuInstance::new()).unwrap();
-        let result = block_on(gpu.generate_buffer(&config, &function));
+        let gene = Gene::new(0, ComputeFunction::One(Box::new(function)));
+        let result = block_on(gpu.generate_buffer(&config, &gene));
         println!("{:?}", &result);
     }
+
+    #[test]
+    fn test_invalid_gene_is_an_error() {
+        let config = ImageConfig {
+            resolution: Resolution::new(4, 4),
+            bounds: Bounds::new(0.0, 0.0, 0.0, 1.0, 1.0),
+        };
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        let gene = Gene::new(3, "cos(_)".parse().unwrap());
+        let result = block_on(gpu.generate_buffer(&config, &gene));
+        assert!(matches!(result, Err(RenderError::Placeholder)));
+    }
+
+    #[test]
+    fn test_device_lost() {
+        let config = ImageConfig {
+            resolution: Resolution::new(4, 4),
+            bounds: Bounds::new(0.0, 0.0, 0.0, 1.0, 1.0),
+        };
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        let gene = Gene::new(0, "sin(x)".parse().unwrap());
+        assert!(block_on(gpu.generate_buffer(&config, &gene)).is_ok());
+        gpu.device.destroy();
+        gpu.device.poll(wgpu::Maintain::Wait);
+        let result = block_on(gpu.generate_buffer(&config, &gene));
+        assert!(
+            matches!(result, Err(RenderError::DeviceLost(_))),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_render_batch() {
+        let config = ImageConfig {
+            resolution: Resolution::new(12, 10),
+            bounds: Bounds::new(-1.0, -1.0, 0.0, 2.0, 2.0),
+        };
+        let mut rng = GeneRng::seed_from_u64(1);
+        let mut genes: Vec<_> = (0..64)
+            .map(|seed| Gene::random(seed, GenerationMethod::Grow, 1, 5).unwrap())
+            .collect();
+        genes.insert(3, Gene::new(0, "cos(_)".parse().unwrap()));
+        genes.shuffle(&mut rng);
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        let results = block_on(gpu.render_batch(&genes, &config)).unwrap();
+        assert_eq!(results.len(), genes.len());
+        for (gene, result) in genes.iter().zip(results) {
+            match block_on(gpu.generate_buffer(&config, gene)) {
+                Ok(expected) => assert_close(gene.function(), &result.unwrap(), &expected, 0.0),
+                Err(_) => assert!(matches!(result, Err(RenderError::Placeholder))),
+            }
+        }
+    }
+
+    #[test]
+    fn test_failed_pipeline_stays_in_its_slot() {
+        let config = ImageConfig {
+            resolution: Resolution::new(6, 5),
+            bounds: Bounds::new(-1.0, -1.0, 0.0, 2.0, 2.0),
+        };
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        let genes = [
+            Gene::new(0, "sin(x) * y".parse().unwrap()),
+            Gene::new(1, "avg(x, rgb(1, 0.5, 0))".parse().unwrap()),
+        ];
+        let mut prepared: Vec<_> = genes
+            .iter()
+            .map(|gene| block_on(gpu.prepare(gene)))
+            .collect();
+        // Generated shaders compile on every adapter we test on, so fail a pipeline by naming a missing entry point
+        let shader_code = genes[0].shader_code(ConstantMode::Inline).unwrap();
+        let failed = block_on(gpu.error_scope(|| {
+            let module = gpu
+                .device
+                .create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: None,
+                    source: wgpu::ShaderSource::Wgsl(shader_code.into()),
+                });
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: None,
+                    layout: None,
+                    module: &module,
+                    entry_point: "missing",
+                })
+        }));
+        prepared.insert(1, Err(failed.unwrap_err()));
+
+        let results = block_on(gpu.render_prepared(prepared, &config)).unwrap();
+        assert!(matches!(results[1], Err(RenderError::Validation(_))));
+        for (gene, result) in genes.iter().zip([&results[0], &results[2]]) {
+            assert_eq!(
+                result.as_ref().unwrap(),
+                &block_on(gpu.generate_buffer(&config, gene)).unwrap()
+            );
+        }
+    }
 }
@@ -0,0 +1,109 @@
+use crate::{
+    compute_functions::{
+        image::{ImageConfig, Tile},
+        shader_builder::FRAME_WORKGROUP_SIZE,
+    },
+    error::RenderError,
+    gene::Gene,
+};
+
+use super::{context::BYTES_PER_PIXEL, instance::GpuInstance};
+
+/// Largest number of pixels and largest side a tile may have within `limits`
+fn tile_limits(limits: &wgpu::Limits) -> (u32, u32) {
+    let max_bytes = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+    let max_pixels = (max_bytes / BYTES_PER_PIXEL).min(u32::MAX as u64) as u32;
+    // Saturates, as adapter limits can be close to u32::MAX
+    let max_side = limits
+        .max_compute_workgroups_per_dimension
+        .saturating_mul(FRAME_WORKGROUP_SIZE[0].min(FRAME_WORKGROUP_SIZE[1]));
+    (max_pixels, max_side)
+}
+
+impl GpuInstance {
+    /// Splits `image_config` into tiles that fit within the device's buffer and dispatch limits
+    pub fn tiles(&self, image_config: &ImageConfig) -> Vec<Tile> {
+        let (max_pixels, max_side) = tile_limits(&self.device.limits());
+        image_config.tiles(max_pixels, max_side)
+    }
+
+    /// Renders each tile in turn, passing its buffer to `on_tile` as soon as it is read back
+    pub async fn render_tiles(
+        &self,
+        tiles: &[Tile],
+        gene: &Gene,
+        mut on_tile: impl FnMut(&Tile, Vec<f32>),
+    ) -> Result<(), RenderError> {
+        for tile in tiles {
+            let buffer = self.generate_buffer(&tile.config, gene).await?;
+            on_tile(tile, buffer);
+        }
+        Ok(())
+    }
+
+    /// Renders `gene` at any resolution, splitting it into tiles when one buffer would exceed the device limits
+    ///
+    /// The result has the same layout as `generate_buffer`
+    pub async fn generate_tiled_buffer(
+        &self,
+        image_config: &ImageConfig,
+        gene: &Gene,
+    ) -> Result<Vec<f32>, RenderError> {
+        let tiles = self.tiles(image_config);
+        if let [tile] = tiles.as_slice() {
+            return self.generate_buffer(&tile.config, gene).await;
+        }
+        let mut result = vec![0.0; image_config.pixels() as usize * 3];
+        self.render_tiles(&tiles, gene, |tile, buffer| {
+            tile.stitch(&buffer, &mut result, image_config.resolution.0)
+        })
+        .await?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::block_on;
+
+    use crate::compute_functions::image::{Bounds, Resolution};
+
+    use super::*;
+
+    #[test]
+    fn test_tiles_match_single_render() {
+        let config = ImageConfig {
+            resolution: Resolution::new(45, 19),
+            bounds: Bounds::new(-2.0, -1.0, 0.5, 4.0, 3.0),
+        };
+        let gene = Gene::new(0, "sin(x * y) + rgb(0, 1, 0) * y".parse().unwrap());
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        assert_eq!(gpu.tiles(&config).len(), 1);
+        let expected = block_on(gpu.generate_tiled_buffer(&config, &gene)).unwrap();
+
+        let tiles = config.tiles(100, 16);
+        assert!(tiles.len() > 1);
+        let mut result = vec![f32::NAN; expected.len()];
+        block_on(gpu.render_tiles(&tiles, &gene, |tile, buffer| {
+            tile.stitch(&buffer, &mut result, config.resolution.0)
+        }))
+        .unwrap();
+        for (tiled, whole) in result.iter().zip(&expected) {
+            assert!((tiled - whole).abs() < 1e-4, "{} != {}", tiled, whole);
+        }
+    }
+
+    #[test]
+    fn test_huge_limits_saturate() {
+        let limits = wgpu::Limits {
+            max_compute_workgroups_per_dimension: u32::MAX,
+            max_storage_buffer_binding_size: u32::MAX,
+            max_buffer_size: u64::MAX,
+            ..Default::default()
+        };
+        assert_eq!(
+            tile_limits(&limits),
+            ((u32::MAX as u64 / BYTES_PER_PIXEL) as u32, u32::MAX)
+        );
+    }
+}
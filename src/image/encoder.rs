@@ -1,18 +1,142 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::Path,
+};
+
+use tiff::encoder::{colortype::RGB8, TiffEncoder, TiffKind, TiffKindBig, TiffKindStandard};
+
+use crate::{
+    backend::RenderBackend,
+    compute_functions::image::{ImageConfig, Tile},
+    error::EncodeError,
+    gene::Gene,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Tiff,
+}
+
+impl ImageFormat {
+    /// Guesses the format from a file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "tif" | "tiff" => Some(ImageFormat::Tiff),
+            _ => None,
+        }
+    }
+}
+
+/// Converts an interleaved rgb float buffer to 8 bit channels, clamping to [0, 1] and mapping NaN to 0
+pub fn to_rgb8(buffer: &[f32]) -> Vec<u8> {
+    buffer.iter().map(|v| (v * 255.0).floor() as u8).collect()
+}
+
+/// Largest 8 bit rgb image a standard tiff can hold, as its offsets are 32 bit. Leaves room for the header and tags
+const MAX_STANDARD_TIFF_BYTES: u64 = u32::MAX as u64 - (1 << 20);
+
+/// Encodes an image one band of rows at a time, so only a single band is held in memory
+///
+/// * `band_rows` - Rows rendered per call to `backend`, which bounds peak memory
+pub fn encode_bands<W: Write + Seek>(
+    writer: W,
+    format: ImageFormat,
+    image_config: &ImageConfig,
+    band_rows: u32,
+    backend: &dyn RenderBackend,
+    gene: &Gene,
+) -> Result<(), EncodeError> {
+    let bands = image_config.bands(band_rows);
+    let mut next_band = |band: &ImageConfig| -> Result<Vec<u8>, EncodeError> {
+        Ok(to_rgb8(&backend.render(gene, band)?))
+    };
+    let (width, height) = (image_config.resolution.0, image_config.resolution.1);
+    match format {
+        ImageFormat::Png => {
+            let mut encoder = png::Encoder::new(writer, width, height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut png_writer = encoder.write_header()?;
+            let mut stream = png_writer.stream_writer()?;
+            for band in &bands {
+                stream.write_all(&next_band(&band.config)?)?;
+            }
+            stream.finish()?;
+            png_writer.finish()?;
+        }
+        ImageFormat::Tiff => {
+            let big = width as u64 * height as u64 * 3 > MAX_STANDARD_TIFF_BYTES;
+            if big {
+                encode_tiff::<_, TiffKindBig>(writer, image_config, &bands, &mut next_band)?;
+            } else {
+                encode_tiff::<_, TiffKindStandard>(writer, image_config, &bands, &mut next_band)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes each band as one tiff strip
+fn encode_tiff<W: Write + Seek, K: TiffKind>(
+    writer: W,
+    image_config: &ImageConfig,
+    bands: &[Tile],
+    next_band: &mut impl FnMut(&ImageConfig) -> Result<Vec<u8>, EncodeError>,
+) -> Result<(), EncodeError> {
+    let mut encoder = TiffEncoder::<W, K>::new_generic(writer)?;
+    let mut image =
+        encoder.new_image::<RGB8>(image_config.resolution.0, image_config.resolution.1)?;
+    if let Some(band) = bands.first() {
+        image.rows_per_strip(band.config.resolution.1)?;
+    }
+    for band in bands {
+        image.write_strip(&next_band(&band.config)?)?;
+    }
+    image.finish()?;
+    Ok(())
+}
+
+/// Streams an image to `path`, choosing the format from its extension
+pub fn encode_file(
+    path: &Path,
+    image_config: &ImageConfig,
+    band_rows: u32,
+    backend: &dyn RenderBackend,
+    gene: &Gene,
+) -> Result<(), EncodeError> {
+    let format = ImageFormat::from_path(path)
+        .ok_or_else(|| EncodeError::UnsupportedFormat(path.display().to_string()))?;
+    let writer = BufWriter::new(File::create(path)?);
+    encode_bands(writer, format, image_config, band_rows, backend, gene)
+}
+
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
+
     use pollster::block_on;
 
     use crate::{
+        backend::{CpuBackend, GpuBackend},
         compute_functions::{
             image::{Bounds, ImageConfig},
             ComputeFunction, ConstantFunction, SingleArgFunction,
         },
+        error::RenderError,
         gpu::instance::GpuInstance,
     };
 
-    use crate::compute_functions::image::Resolution;
+    use std::{io::Cursor, path::PathBuf};
+
+    use crate::{compute_functions::image::Resolution, cpu};
     use image::RgbImage;
 
+    use super::*;
+
     fn test_render() -> Vec<f32> {
         let function =
             SingleArgFunction::Sin(ComputeFunction::Zero(Box::new(ConstantFunction::Coord(0))));
@@ -21,7 +145,8 @@ mod tests {
             bounds: Bounds::new(0.0, 0.0, 0.0, 1.0, 1.0),
         };
         let gpu = block_on(GpuInstance::new()).unwrap();
-        let result = block_on(gpu.generate_buffer(&config, &function)).unwrap();
+        let gene = Gene::new(0, ComputeFunction::One(Box::new(function)));
+        let result = block_on(gpu.generate_buffer(&config, &gene)).unwrap();
         println!("{:?}", &result);
         result
     }
@@ -31,12 +156,89 @@ mod tests {
         rgb_image.save(path).unwrap();
     }
 
+    /// Path in the temp dir unique to this process and `test`, so concurrent test runs don't share files
+    fn temp_path(test: &str, extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "ae-gen-{}-{}.{}",
+            std::process::id(),
+            test,
+            extension
+        ))
+    }
+
     #[test]
     fn test_encode() {
-        let data: Vec<u8> = test_render()
-            .iter()
-            .map(|x| (x * 255.0).floor() as u8)
-            .collect();
-        encode_image(&Resolution::new(10, 10), data, "test/test.png");
+        let data = to_rgb8(&test_render());
+        let path = temp_path("test_encode", "png");
+        encode_image(&Resolution::new(10, 10), data, path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn streaming_config() -> ImageConfig {
+        ImageConfig {
+            resolution: Resolution::new(37, 23),
+            bounds: Bounds::new(-1.0, -1.0, 0.0, 2.0, 2.0),
+        }
+    }
+
+    /// Records the largest band it is asked to render
+    struct BandSizes<B: RenderBackend> {
+        backend: B,
+        largest: Cell<u32>,
+    }
+
+    impl<B: RenderBackend> RenderBackend for BandSizes<B> {
+        fn name(&self) -> &'static str {
+            self.backend.name()
+        }
+
+        fn render(&self, gene: &Gene, image_config: &ImageConfig) -> Result<Vec<f32>, RenderError> {
+            self.largest
+                .set(self.largest.get().max(image_config.pixels()));
+            self.backend.render(gene, image_config)
+        }
+    }
+
+    #[test]
+    fn test_stream_png_from_gpu() {
+        let config = streaming_config();
+        let gene = Gene::new(0, "sin(x * rgb(3, 1, 2)) * y".parse().unwrap());
+        let gpu = block_on(GpuInstance::new()).unwrap();
+        let expected = to_rgb8(&block_on(gpu.generate_buffer(&config, &gene)).unwrap());
+
+        let mut bytes = Cursor::new(vec![]);
+        let backend = BandSizes {
+            backend: GpuBackend::new(gpu),
+            largest: Cell::new(0),
+        };
+        encode_bands(&mut bytes, ImageFormat::Png, &config, 5, &backend, &gene).unwrap();
+        assert_eq!(backend.largest.get(), 37 * 5);
+        let decoded = image::load_from_memory(bytes.get_ref()).unwrap().to_rgb8();
+        assert_eq!(decoded.dimensions(), (37, 23));
+        for (a, b) in decoded.as_raw().iter().zip(&expected) {
+            // Bands sample from rebased bounds, which can shift a channel across a rounding boundary
+            assert!(a.abs_diff(*b) <= 1, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_stream_tiff_from_cpu() {
+        let config = streaming_config();
+        let gene = Gene::new(0, "avg(x, y) + rgb(0.5, 0.25, 0)".parse().unwrap());
+        let expected = to_rgb8(&cpu::processing::generate_buffer(&config, gene.function()));
+
+        let path = temp_path("test_stream_tiff_from_cpu", "tiff");
+        let backend = CpuBackend::default();
+        encode_file(&path, &config, 4, &backend, &gene).unwrap();
+        let decoded = image::open(&path).unwrap().to_rgb8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decoded.dimensions(), (37, 23));
+        for (a, b) in decoded.as_raw().iter().zip(&expected) {
+            assert!(a.abs_diff(*b) <= 1, "{} != {}", a, b);
+        }
+        assert!(matches!(
+            encode_file(Path::new("image.bmp"), &config, 4, &backend, &gene),
+            Err(EncodeError::UnsupportedFormat(_))
+        ));
     }
 }
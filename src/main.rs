@@ -1,5 +1,8 @@
+pub mod backend;
 pub mod compute_functions;
+pub mod cpu;
 pub mod error;
+pub mod evolution;
 pub mod gene;
 pub mod gpu;
 pub mod image;